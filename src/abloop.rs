@@ -0,0 +1,120 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use rodio::source::SeekError;
+use rodio::Source;
+
+/// A section of the current track to play over and over.
+#[derive(Clone, Copy, PartialEq)]
+pub struct AbLoop {
+    pub a: Duration,
+    pub b: Duration,
+    /// How many times to jump back to A before playing on; `None` loops forever.
+    pub repeats: Option<u32>,
+}
+
+/// Loop points set from the UI, and how often the playback thread has gone round.
+#[derive(Clone, Copy, Default)]
+pub struct LoopState {
+    pub points: Option<AbLoop>,
+    pub passes: u32,
+    /// Set when the loop was dropped because the track can't seek back to A.
+    pub failed: bool,
+}
+
+/// Jumps back to A whenever playback reaches B, by seeking the source it wraps.
+/// `position` is the source's position in seconds, as f64 bits.
+pub struct Looper<S> {
+    input: S,
+    shared: Arc<Mutex<LoopState>>,
+    state: LoopState,
+    position: Arc<AtomicU64>,
+    // Index of the next sample within its frame; jumps only happen between frames
+    channel: usize,
+}
+
+impl<S: Source> Looper<S> {
+    pub fn new(input: S, shared: Arc<Mutex<LoopState>>, position: Arc<AtomicU64>) -> Self {
+        let state = *shared.lock().unwrap();
+        Self { input, shared, state, position, channel: 0 }
+    }
+
+    /// Picks up loop points changed from the UI.
+    pub fn refresh(&mut self) {
+        self.state = *self.shared.lock().unwrap();
+    }
+
+    pub fn inner_mut(&mut self) -> &mut S {
+        &mut self.input
+    }
+
+    fn current_position(&self) -> Duration {
+        Duration::from_secs_f64(f64::from_bits(self.position.load(Ordering::Relaxed)))
+    }
+
+    fn jump_if_past_b(&mut self) {
+        let Some(points) = self.state.points else { return };
+        if points.repeats.is_some_and(|repeats| self.state.passes >= repeats) || self.current_position() < points.b {
+            return;
+        }
+
+        let seeked = self.input.try_seek(points.a).is_ok();
+        if seeked {
+            self.state.passes += 1;
+        } else {
+            // Nothing we can loop on; play on as if there were no loop
+            self.state.points = None;
+        }
+
+        let mut shared = self.shared.lock().unwrap();
+        // Leave loop points the UI has replaced meanwhile alone
+        if shared.points == Some(points) {
+            if seeked {
+                shared.passes = self.state.passes;
+            } else {
+                *shared = LoopState { points: None, passes: 0, failed: true };
+            }
+        }
+    }
+}
+
+impl<S: Source> Iterator for Looper<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.channel == 0 {
+            self.jump_if_past_b();
+        }
+        let sample = self.input.next()?;
+        self.channel = (self.channel + 1) % (self.input.channels() as usize).max(1);
+        Some(sample)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<S: Source> Source for Looper<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        self.input.current_span_len()
+    }
+
+    fn channels(&self) -> rodio::ChannelCount {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> rodio::SampleRate {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)?;
+        self.channel = 0;
+        Ok(())
+    }
+}
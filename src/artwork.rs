@@ -0,0 +1,47 @@
+use std::path::{Path, PathBuf};
+use eframe::egui::ColorImage;
+use crate::audio::read_embedded_artwork;
+use crate::worker::BackgroundWorker;
+
+/// Artwork is scaled down to fit in a square this many pixels across.
+pub const ARTWORK_SIZE: u32 = 128;
+
+// Pictures looked for next to a track that has none of its own, best
+// first; any other picture will do if none of these is there
+const COVER_FILE_NAMES: &[&str] = &["cover", "folder", "front", "album"];
+const COVER_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png"];
+
+/// Cover art of the album a track is on: the picture embedded in the
+/// track, or else a cover image in its folder.
+pub fn load_artwork(track: &Path) -> Option<ColorImage> {
+    let data = read_embedded_artwork(track)
+        .map(Vec::from)
+        .or_else(|| std::fs::read(find_cover_file(track.parent()?)?).ok())?;
+
+    let image = image::load_from_memory(&data).ok()?.thumbnail(ARTWORK_SIZE, ARTWORK_SIZE).to_rgba8();
+    let size = [image.width() as usize, image.height() as usize];
+    Some(ColorImage::from_rgba_unmultiplied(size, image.as_raw()))
+}
+
+fn find_cover_file(folder: &Path) -> Option<PathBuf> {
+    let lowercase = |part: Option<&std::ffi::OsStr>| part.and_then(|p| p.to_str()).map(str::to_lowercase);
+    let mut pictures: Vec<PathBuf> = std::fs::read_dir(folder)
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| lowercase(path.extension()).is_some_and(|ext| COVER_EXTENSIONS.contains(&ext.as_str())))
+        .collect();
+    pictures.sort_by_key(|path| {
+        let stem = lowercase(path.file_stem()).unwrap_or_default();
+        COVER_FILE_NAMES.iter().position(|name| *name == stem).unwrap_or(COVER_FILE_NAMES.len())
+    });
+    pictures.into_iter().next()
+}
+
+/// Loads artwork on a worker thread so the UI never waits on decoding.
+pub type ArtworkLoader = BackgroundWorker<Option<ColorImage>>;
+
+impl ArtworkLoader {
+    pub fn new() -> Self {
+        Self::spawn(load_artwork)
+    }
+}
@@ -1,58 +1,483 @@
 use std::path::{Path, PathBuf};
 use walkdir::WalkDir;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use symphonia::core::audio::{SampleBuffer, SignalSpec};
+use symphonia::core::codecs::DecoderOptions;
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::{FormatReader, SeekMode, SeekTo};
 use symphonia::core::io::MediaSourceStream;
-use symphonia::core::probe::Hint;
+use symphonia::core::meta::{StandardTagKey, StandardVisualKey, Tag, Visual};
+use symphonia::core::probe::{Hint, ProbeResult};
+use symphonia::core::units::TimeBase;
+use crate::library::{TrackId, TrackStats};
+use crate::replaygain::ReplayGain;
+use crate::worker::BackgroundWorker;
 
 const AUDIO_EXTENSIONS: &[&str] = &["mp3", "wav"];
 
+// Packets read to estimate the length of a file that doesn't give one
+const ESTIMATE_PACKETS: usize = 200;
+
+// How much of the start, and of the middle, of the audio goes into a TrackId
+const TRACK_ID_BYTES: usize = 64 * 1024;
+const TRACK_ID_MIDDLE_BYTES: usize = 16 * 1024;
+
+/// Whether a duration came from the container header or had to be worked out some other way.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum DurationAccuracy {
+    Exact,
+    Estimated,
+}
+
+/// Technical details of the encoded stream, mostly useful for telling a
+/// lossless rip from a transcode.
+#[derive(Clone, Default)]
+pub struct AudioInfo {
+    pub codec: Option<String>,
+    pub codec_description: Option<String>,
+    pub sample_rate: Option<u32>,
+    pub bits_per_sample: Option<u32>,
+    pub channels: Option<usize>,
+    /// Average bitrate over the whole file, in kbit/s.
+    pub bitrate: Option<u32>,
+    // Size of the file less its tags and artwork, for working out the bitrate
+    audio_bytes: Option<u64>,
+}
+
+impl AudioInfo {
+    // Uncompressed audio has a fixed bitrate; anything else is averaged over
+    // the audio in the file, so a big cover doesn't count towards it
+    fn update_bitrate(&mut self, duration: Duration) {
+        let is_pcm = self.codec.as_deref().is_some_and(|codec| codec.starts_with("pcm"));
+        if is_pcm
+            && let (Some(rate), Some(bits), Some(channels)) = (self.sample_rate, self.bits_per_sample, self.channels)
+        {
+            self.bitrate = Some(rate * bits * channels as u32 / 1000);
+        } else if let Some(bytes) = self.audio_bytes
+            && duration.as_secs_f64() > 0.0
+        {
+            let kbps = bytes as f64 * 8.0 / duration.as_secs_f64() / 1000.0;
+            self.bitrate = Some(kbps.round() as u32);
+        }
+    }
+
+    pub fn channel_layout(&self) -> Option<String> {
+        self.channels.map(|count| match count {
+            1 => "Mono".to_string(),
+            2 => "Stereo".to_string(),
+            n => format!("{} channels", n),
+        })
+    }
+}
+
+/// Descriptive tags of a track, as far as the file has them.
+#[derive(Clone, Default)]
+pub struct TrackTags {
+    pub artist: Option<String>,
+    pub album_artist: Option<String>,
+    pub album: Option<String>,
+    pub genre: Option<String>,
+    pub year: Option<u32>,
+    pub track_number: Option<u32>,
+    pub disc_number: Option<u32>,
+}
+
+impl TrackTags {
+    fn from_tags(tags: &[Tag]) -> Self {
+        let mut track_tags = Self::default();
+
+        for tag in tags {
+            let value = tag.value.to_string().trim().to_string();
+            if value.is_empty() {
+                continue;
+            }
+            match tag.std_key {
+                Some(StandardTagKey::Artist) => track_tags.artist = Some(value),
+                Some(StandardTagKey::AlbumArtist) => track_tags.album_artist = Some(value),
+                Some(StandardTagKey::Album) => track_tags.album = Some(value),
+                Some(StandardTagKey::Genre) => track_tags.genre = Some(value),
+                Some(StandardTagKey::Date | StandardTagKey::ReleaseDate | StandardTagKey::OriginalDate) => {
+                    // Dates come as anything from "1997" to "1997-05-21T00:00:00"
+                    track_tags.year = track_tags.year.or_else(|| value.get(..4)?.parse().ok());
+                }
+                Some(StandardTagKey::TrackNumber) => track_tags.track_number = parse_position(&value),
+                Some(StandardTagKey::DiscNumber) => track_tags.disc_number = parse_position(&value),
+                _ => {}
+            }
+        }
+
+        track_tags
+    }
+}
+
+impl TrackTags {
+    /// Who the track's album is filed under.
+    pub fn album_artist(&self) -> Option<&str> {
+        self.album_artist.as_deref().or(self.artist.as_deref())
+    }
+}
+
+// Track and disc numbers are often written as "3/12"
+fn parse_position(value: &str) -> Option<u32> {
+    value.split('/').next()?.trim().parse().ok()
+}
+
 #[derive(Clone)]
 pub struct AudioFile {
     pub path: PathBuf,
     pub duration: Option<Duration>,
+    pub duration_accuracy: DurationAccuracy,
     pub title: String,
+    pub info: AudioInfo,
+    pub replay_gain: ReplayGain,
+    pub tags: TrackTags,
+    /// When the file turned up on disk.
+    pub added: Option<SystemTime>,
+    pub id: Option<TrackId>,
+    /// Filled in from the library by `Library::apply`.
+    pub stats: TrackStats,
 }
 
 impl AudioFile {
     pub fn new(path: PathBuf) -> Self {
-        let duration = get_audio_duration(&path);
+        let probed = probe_audio(&path);
+        let (duration, duration_accuracy) = match probed.duration {
+            Some((duration, accuracy)) => (Some(duration), accuracy),
+            None => (None, DurationAccuracy::Estimated),
+        };
         let title = path.file_name()
             .and_then(|n| n.to_str())
             .unwrap_or("Unknown")
             .to_string();
+        let added = std::fs::metadata(&path)
+            .ok()
+            .and_then(|metadata| metadata.created().or_else(|_| metadata.modified()).ok());
         
-        Self { path, duration, title }
+        Self {
+            path,
+            duration,
+            duration_accuracy,
+            title,
+            info: probed.info,
+            replay_gain: probed.replay_gain,
+            tags: probed.tags,
+            added,
+            id: probed.id,
+            stats: TrackStats::default(),
+        }
+    }
+
+    pub fn is_duration_estimated(&self) -> bool {
+        self.duration_accuracy == DurationAccuracy::Estimated
+    }
+
+    /// Replaces the duration, and the average bitrate that depends on it.
+    pub fn set_duration(&mut self, duration: Duration, accuracy: DurationAccuracy) {
+        self.duration = Some(duration);
+        self.duration_accuracy = accuracy;
+        self.info.update_bitrate(duration);
     }
 }
 
-fn get_audio_duration(path: &Path) -> Option<Duration> {
-    let file = File::open(path).ok()?;
+#[derive(Default)]
+struct ProbedAudio {
+    duration: Option<(Duration, DurationAccuracy)>,
+    info: AudioInfo,
+    replay_gain: ReplayGain,
+    tags: TrackTags,
+    id: Option<TrackId>,
+}
+
+fn probe_audio(path: &Path) -> ProbedAudio {
+    let audio_bytes = audio_bytes(path).ok();
+    let mut probed = read_stream(path, audio_bytes).unwrap_or_default();
+
+    // Only the size of the audio is left to know for the average bitrate
+    probed.info.audio_bytes = audio_bytes;
+    if let Some((duration, _)) = probed.duration {
+        probed.info.update_bitrate(duration);
+    }
+
+    probed
+}
+
+/// Size of a file less any ID3v2 tag at the start and ID3v1 tag at the end,
+/// which is where tags and embedded pictures live in an MP3.
+fn audio_bytes(path: &Path) -> std::io::Result<u64> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+
+    let mut tag_bytes = 0;
+    let mut header = [0u8; 10];
+    if file.read_exact(&mut header).is_ok() && &header[..3] == b"ID3" {
+        // Tag size is stored 7 bits a byte, not counting the header or footer
+        let size = header[6..10].iter().fold(0u64, |size, byte| (size << 7) | (byte & 0x7f) as u64);
+        let footer = if header[5] & 0x10 != 0 { 10 } else { 0 };
+        tag_bytes += 10 + size + footer;
+    }
+    if len >= 128 {
+        let mut trailer = [0u8; 3];
+        file.seek(SeekFrom::Start(len - 128))?;
+        if file.read_exact(&mut trailer).is_ok() && &trailer == b"TAG" {
+            tag_bytes += 128;
+        }
+    }
+
+    Ok(len.saturating_sub(tag_bytes))
+}
+
+/// Opens a file and works out its container format.
+fn probe_file(path: &Path) -> Result<ProbeResult, Box<dyn std::error::Error>> {
+    let file = File::open(path)?;
     let mss = MediaSourceStream::new(Box::new(file), Default::default());
 
     let mut hint = Hint::new();
     if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
         hint.with_extension(ext);
     }
-    
-    // Probe the media source for metadata
-    let format_opts = Default::default();
-    let metadata_opts = Default::default();
-    
-    let probed = symphonia::default::get_probe()
-        .format(&hint, mss, &format_opts, &metadata_opts)
-        .ok()?;
-    
+
+    Ok(symphonia::default::get_probe().format(&hint, mss, &Default::default(), &Default::default())?)
+}
+
+fn read_stream(path: &Path, audio_bytes: Option<u64>) -> Option<ProbedAudio> {
+    let mut probed = probe_file(path).ok()?;
+
+    let tags = read_tags(&mut probed);
+    let replay_gain = ReplayGain::from_tags(&tags);
+    let track_tags = TrackTags::from_tags(&tags);
+
     // Get the default track
     let track = probed.format.default_track()?;
-    
-    // Calculate duration from time base and number of frames
+    let track_id = track.id;
+    let params = &track.codec_params;
+    let (time_base, n_frames) = (params.time_base, params.n_frames);
+
+    let codec = symphonia::default::get_codecs().get_codec(params.codec);
+    let info = AudioInfo {
+        codec: codec.map(|c| c.short_name.to_string()),
+        codec_description: codec.map(|c| c.long_name.to_string()),
+        sample_rate: params.sample_rate,
+        bits_per_sample: params.bits_per_sample,
+        channels: params.channels.map(|c| c.count()),
+        bitrate: None,
+        audio_bytes: None,
+    };
+
+    let (frames, id) = identify(probed.format.as_mut(), track_id, n_frames, audio_bytes);
+    let accuracy = if n_frames.is_some() { DurationAccuracy::Exact } else { DurationAccuracy::Estimated };
+
+    let duration = time_base
+        .zip(frames)
+        .map(|(time_base, frames)| (timestamp_to_duration(time_base, frames), accuracy));
+
+    Some(ProbedAudio { duration, info, replay_gain, tags: track_tags, id })
+}
+
+/// Identifies a file's audio the way `AudioFile::id` does, without reading
+/// anything else about it.
+pub fn read_track_id(path: &Path) -> Option<TrackId> {
+    let mut probed = probe_file(path).ok()?;
+    let track = probed.format.default_track()?;
+    let (track_id, n_frames) = (track.id, track.codec_params.n_frames);
+    identify(probed.format.as_mut(), track_id, n_frames, audio_bytes(path).ok()).1
+}
+
+// Works out a track's length in frames, when the container doesn't say, and
+// its TrackId, from the start of its audio and a stretch from the middle
+fn identify(
+    format: &mut dyn FormatReader,
+    track_id: u32,
+    n_frames: Option<u64>,
+    audio_bytes: Option<u64>,
+) -> (Option<u64>, Option<TrackId>) {
+    let sample = sample_packets(format, track_id);
+
+    // VBR MP3s without a Xing/VBRI header don't report a frame count.
+    // Walking every frame header is too slow for a scan, so guess from the
+    // first few and leave the full walk to `DurationScanner`.
+    let frames = n_frames.or_else(|| audio_bytes.and_then(|bytes| sample.estimate_frame_count(bytes)));
+
+    let id = (!sample.head.is_empty()).then(|| {
+        let middle = frames.map(|frames| read_middle(format, track_id, frames)).unwrap_or_default();
+        TrackId::from_audio(&sample.head, frames.unwrap_or(0), &middle)
+    });
+    (frames, id)
+}
+
+/// Collects tags found ahead of the container (e.g. ID3v2) and inside it.
+fn read_tags(probed: &mut ProbeResult) -> Vec<Tag> {
+    let mut tags = Vec::new();
+
+    if let Some(metadata) = probed.metadata.get()
+        && let Some(revision) = metadata.current()
+    {
+        tags.extend_from_slice(revision.tags());
+    }
+    if let Some(revision) = probed.format.metadata().current() {
+        tags.extend_from_slice(revision.tags());
+    }
+
+    tags
+}
+
+/// Picture embedded in the file, the front cover if there's more than one.
+pub fn read_embedded_artwork(path: &Path) -> Option<Box<[u8]>> {
+    let mut probed = probe_file(path).ok()?;
+
+    let mut visuals: Vec<Visual> = Vec::new();
+    if let Some(metadata) = probed.metadata.get()
+        && let Some(revision) = metadata.current()
+    {
+        visuals.extend_from_slice(revision.visuals());
+    }
+    if let Some(revision) = probed.format.metadata().current() {
+        visuals.extend_from_slice(revision.visuals());
+    }
+
+    let front = visuals.iter().position(|visual| visual.usage == Some(StandardVisualKey::FrontCover));
+    let visual = visuals.into_iter().nth(front.unwrap_or(0))?;
+    Some(visual.data)
+}
+
+/// What the first few packets of a track tell about the rest of it.
+#[derive(Default)]
+struct PacketSample {
+    n_frames: u64,
+    bytes: u64,
+    /// Whether they were all the packets there are.
+    complete: bool,
+    /// The start of the audio itself, which tag edits don't touch.
+    head: Vec<u8>,
+}
+
+impl PacketSample {
+    /// Scales the frames sampled up to the size of the audio.
+    fn estimate_frame_count(&self, audio_bytes: u64) -> Option<u64> {
+        if self.n_frames == 0 || self.bytes == 0 {
+            return None;
+        }
+        if self.complete {
+            return Some(self.n_frames);
+        }
+        Some((self.n_frames as f64 * audio_bytes as f64 / self.bytes as f64) as u64)
+    }
+}
+
+fn sample_packets(format: &mut dyn FormatReader, track_id: u32) -> PacketSample {
+    let mut sample = PacketSample::default();
+    let mut packets = 0;
+    while packets < ESTIMATE_PACKETS || sample.head.len() < TRACK_ID_BYTES {
+        let Ok(packet) = format.next_packet() else {
+            sample.complete = true;
+            break;
+        };
+        if packet.track_id() == track_id {
+            sample.n_frames += packet.dur;
+            sample.bytes += packet.data.len() as u64;
+            let wanted = TRACK_ID_BYTES.saturating_sub(sample.head.len()).min(packet.data.len());
+            sample.head.extend_from_slice(&packet.data[..wanted]);
+            packets += 1;
+        }
+    }
+    sample
+}
+
+/// Audio data from halfway through a track of `n_frames`, so tracks that
+/// open the same way (e.g. with silence) still tell apart.
+fn read_middle(format: &mut dyn FormatReader, track_id: u32, n_frames: u64) -> Vec<u8> {
+    let mut middle = Vec::new();
+    let to = SeekTo::TimeStamp { ts: n_frames / 2, track_id };
+    if format.seek(SeekMode::Coarse, to).is_err() {
+        return middle;
+    }
+    while middle.len() < TRACK_ID_MIDDLE_BYTES {
+        let Ok(packet) = format.next_packet() else { break };
+        if packet.track_id() == track_id {
+            let wanted = (TRACK_ID_MIDDLE_BYTES - middle.len()).min(packet.data.len());
+            middle.extend_from_slice(&packet.data[..wanted]);
+        }
+    }
+    middle
+}
+
+/// Demuxes every packet of a track without decoding it and sums their lengths.
+fn scan_frame_count(format: &mut dyn FormatReader, track_id: u32) -> Option<u64> {
+    let mut n_frames = 0;
+    while let Ok(packet) = format.next_packet() {
+        if packet.track_id() == track_id {
+            n_frames += packet.dur;
+        }
+    }
+
+    if n_frames > 0 { Some(n_frames) } else { None }
+}
+
+/// Length of a file found by walking all of its frame headers. Encoder delay
+/// and padding are unknown here, which is why it's still only an estimate.
+pub fn scan_duration(path: &Path) -> Option<Duration> {
+    let mut probed = probe_file(path).ok()?;
+    let track = probed.format.default_track()?;
+    let track_id = track.id;
     let time_base = track.codec_params.time_base?;
-    let n_frames = track.codec_params.n_frames?;
-    
-    let duration_secs = (n_frames as f64) * time_base.numer as f64 / time_base.denom as f64;
-    
-    Some(Duration::from_secs_f64(duration_secs))
+
+    scan_frame_count(probed.format.as_mut(), track_id).map(|n_frames| timestamp_to_duration(time_base, n_frames))
+}
+
+/// Walks files' frame headers on a worker thread, since it means reading
+/// each of them all the way through.
+pub type DurationScanner = BackgroundWorker<Option<Duration>>;
+
+impl DurationScanner {
+    pub fn new() -> Self {
+        Self::spawn(scan_duration)
+    }
+}
+
+/// Decodes a whole file, handing each packet's interleaved samples to
+/// `on_samples` as they come.
+pub fn decode_file(path: &Path, mut on_samples: impl FnMut(&[f32], SignalSpec)) -> Result<(), Box<dyn std::error::Error>> {
+    let mut probed = probe_file(path)?;
+    let track = probed.format.default_track().ok_or("No audio track")?;
+    let track_id = track.id;
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&track.codec_params, &DecoderOptions::default())?;
+
+    let mut sample_buf: Option<SampleBuffer<f32>> = None;
+
+    loop {
+        let packet = match probed.format.next_packet() {
+            Ok(packet) => packet,
+            Err(SymphoniaError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e.into()),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            // A corrupt frame shouldn't throw away the whole file
+            Err(SymphoniaError::DecodeError(_)) => continue,
+            Err(e) => return Err(e.into()),
+        };
+
+        let spec = *decoded.spec();
+        let buf = sample_buf.get_or_insert_with(|| SampleBuffer::new(decoded.capacity() as u64, spec));
+        if buf.capacity() < decoded.capacity() * spec.channels.count() {
+            *buf = SampleBuffer::new(decoded.capacity() as u64, spec);
+        }
+        buf.copy_interleaved_ref(decoded);
+        on_samples(buf.samples(), spec);
+    }
+
+    Ok(())
+}
+
+fn timestamp_to_duration(time_base: TimeBase, ts: u64) -> Duration {
+    let duration_secs = (ts as f64) * time_base.numer as f64 / time_base.denom as f64;
+    Duration::from_secs_f64(duration_secs)
 }
 
 fn is_audio_file(path: &Path) -> bool {
@@ -73,10 +498,8 @@ pub fn find_audio_files(path: &Path) -> Result<Vec<AudioFile>, Box<dyn std::erro
                 files.push(AudioFile::new(entry_path.to_path_buf()))
             }
         }
-    } else if path.is_file() {
-        if is_audio_file(path) {
-            files.push(AudioFile::new(path.to_path_buf()));
-        }
+    } else if path.is_file() && is_audio_file(path) {
+        files.push(AudioFile::new(path.to_path_buf()));
     }
     Ok(files)
 } 
\ No newline at end of file
@@ -0,0 +1,190 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use crate::audio::AudioFile;
+use crate::sort::natural_cmp;
+
+/// The ways the library browser can group tracks.
+#[derive(Clone, Copy, PartialEq)]
+pub enum BrowseTab {
+    Artists,
+    Albums,
+    Genres,
+    Years,
+    Folders,
+}
+
+impl BrowseTab {
+    pub const ALL: [BrowseTab; 5] = [
+        BrowseTab::Artists,
+        BrowseTab::Albums,
+        BrowseTab::Genres,
+        BrowseTab::Years,
+        BrowseTab::Folders,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            BrowseTab::Artists => "Artists",
+            BrowseTab::Albums => "Albums",
+            BrowseTab::Genres => "Genres",
+            BrowseTab::Years => "Years",
+            BrowseTab::Folders => "Folders",
+        }
+    }
+}
+
+/// A group of library tracks that can be picked in the browser.
+#[derive(Clone, PartialEq)]
+pub enum BrowseNode {
+    Artist(String),
+    Album { artist: Option<String>, title: String },
+    Genre(String),
+    Year(u32),
+    /// Everything in a folder and the folders below it.
+    Folder(PathBuf),
+}
+
+impl BrowseNode {
+    pub fn contains(&self, file: &AudioFile) -> bool {
+        let tags = &file.tags;
+        match self {
+            BrowseNode::Artist(artist) => {
+                tags.album_artist() == Some(artist.as_str()) || tags.artist.as_ref() == Some(artist)
+            }
+            BrowseNode::Album { artist, title } => {
+                tags.album.as_ref() == Some(title) && tags.album_artist() == artist.as_deref()
+            }
+            BrowseNode::Genre(genre) => tags.genre.as_ref() == Some(genre),
+            BrowseNode::Year(year) => tags.year == Some(*year),
+            BrowseNode::Folder(folder) => file.path.starts_with(folder),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Album {
+    pub title: String,
+    pub artist: Option<String>,
+    pub year: Option<u32>,
+    /// One of the album's tracks, to take the cover art from.
+    pub artwork_track: PathBuf,
+}
+
+impl Album {
+    pub fn node(&self) -> BrowseNode {
+        BrowseNode::Album { artist: self.artist.clone(), title: self.title.clone() }
+    }
+}
+
+pub struct FolderNode {
+    pub path: PathBuf,
+    pub name: String,
+    pub children: Vec<FolderNode>,
+}
+
+impl FolderNode {
+    fn new(path: PathBuf) -> Self {
+        let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_else(|| path.display().to_string());
+        Self { path, name, children: Vec::new() }
+    }
+
+    fn insert(&mut self, relative: &Path) {
+        let mut node = self;
+        for component in relative.components() {
+            let path = node.path.join(component);
+            let index = match node.children.iter().position(|child| child.path == path) {
+                Some(index) => index,
+                None => {
+                    node.children.push(FolderNode::new(path));
+                    node.children.len() - 1
+                }
+            };
+            node = &mut node.children[index];
+        }
+    }
+
+    fn sort(&mut self) {
+        self.children.sort_by(|a, b| natural_cmp(&a.name, &b.name));
+        for child in &mut self.children {
+            child.sort();
+        }
+    }
+}
+
+/// The library's tracks grouped every way the browser shows them, each
+/// group in display order.
+#[derive(Default)]
+pub struct LibraryIndex {
+    /// Every artist with the albums filed under them.
+    pub artists: Vec<(String, Vec<Album>)>,
+    pub albums: Vec<Album>,
+    /// Genres and years with how many tracks they have.
+    pub genres: Vec<(String, usize)>,
+    pub years: Vec<(u32, usize)>,
+    pub folders: Option<FolderNode>,
+}
+
+impl LibraryIndex {
+    /// Groups `files`, which were all found under `root`.
+    pub fn build(files: &[AudioFile], root: &Path) -> Self {
+        let mut albums: Vec<Album> = Vec::new();
+        // Where each (artist, title) is in `albums`
+        let mut album_indices: HashMap<(Option<&str>, &str), usize> = HashMap::new();
+        let mut artist_names: HashSet<&str> = HashSet::new();
+        let mut genres: HashMap<&str, usize> = HashMap::new();
+        let mut years: HashMap<u32, usize> = HashMap::new();
+        let mut folders = FolderNode::new(root.to_path_buf());
+
+        for file in files {
+            let tags = &file.tags;
+            if let Some(title) = &tags.album {
+                match album_indices.get(&(tags.album_artist(), title.as_str())) {
+                    Some(&index) => albums[index].year = albums[index].year.or(tags.year),
+                    None => {
+                        album_indices.insert((tags.album_artist(), title), albums.len());
+                        albums.push(Album {
+                            title: title.clone(),
+                            artist: tags.album_artist().map(str::to_string),
+                            year: tags.year,
+                            artwork_track: file.path.clone(),
+                        });
+                    }
+                }
+            }
+            artist_names.extend(tags.album_artist());
+            artist_names.extend(tags.artist.as_deref());
+            if let Some(genre) = &tags.genre {
+                *genres.entry(genre).or_default() += 1;
+            }
+            if let Some(year) = tags.year {
+                *years.entry(year).or_default() += 1;
+            }
+            if let Some(folder) = file.path.parent().and_then(|parent| parent.strip_prefix(root).ok()) {
+                folders.insert(folder);
+            }
+        }
+
+        albums.sort_by(|a, b| {
+            natural_cmp(a.artist.as_deref().unwrap_or_default(), b.artist.as_deref().unwrap_or_default())
+                .then(a.year.cmp(&b.year))
+                .then_with(|| natural_cmp(&a.title, &b.title))
+        });
+        // Each artist's albums come out in the order they were released
+        let mut artists: Vec<(String, Vec<Album>)> = artist_names
+            .into_iter()
+            .map(|name| {
+                let artist_albums = albums.iter().filter(|album| album.artist.as_deref() == Some(name)).cloned().collect();
+                (name.to_string(), artist_albums)
+            })
+            .collect();
+        artists.sort_by(|(a, _), (b, _)| natural_cmp(a, b));
+
+        let mut genres: Vec<(String, usize)> = genres.into_iter().map(|(genre, count)| (genre.to_string(), count)).collect();
+        genres.sort_by(|(a, _), (b, _)| natural_cmp(a, b));
+        let mut years: Vec<(u32, usize)> = years.into_iter().collect();
+        years.sort();
+        folders.sort();
+
+        Self { artists, albums, genres, years, folders: Some(folders) }
+    }
+}
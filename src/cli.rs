@@ -0,0 +1,30 @@
+use std::path::PathBuf;
+use clap::Parser;
+
+/// What to open and how to start, from the command line.
+#[derive(Parser)]
+#[command(version, about = "Plays audio files, folders and playlists")]
+pub struct Args {
+    /// Audio files, folders or M3U playlists to play, in order
+    pub paths: Vec<PathBuf>,
+
+    /// Play in random order
+    #[arg(long)]
+    pub shuffle: bool,
+
+    /// Starting volume, from 0 to 100
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=100))]
+    pub volume: Option<u8>,
+
+    /// Open one of the saved playlists by name
+    #[arg(long, value_name = "NAME", conflicts_with = "paths")]
+    pub playlist: Option<String>,
+
+    /// Folder to use as the library
+    #[arg(long, value_name = "DIR")]
+    pub library: Option<PathBuf>,
+
+    /// Load what was asked for without starting to play
+    #[arg(long)]
+    pub no_autoplay: bool,
+}
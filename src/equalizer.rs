@@ -0,0 +1,408 @@
+use std::f64::consts::PI;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use rodio::source::SeekError;
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+
+/// Centre frequencies of the graphic EQ, one octave apart.
+pub const GRAPHIC_FREQUENCIES: [f32; 10] = [
+    31.0, 62.0, 125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0, 16000.0,
+];
+pub const MAX_BAND_GAIN: f32 = 12.0;
+
+// Q of a peaking filter whose bandwidth is one octave
+const OCTAVE_Q: f64 = std::f64::consts::SQRT_2;
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum FilterKind {
+    Peak,
+    LowShelf,
+    HighShelf,
+}
+
+impl FilterKind {
+    pub const ALL: [FilterKind; 3] = [FilterKind::Peak, FilterKind::LowShelf, FilterKind::HighShelf];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            FilterKind::Peak => "Peak",
+            FilterKind::LowShelf => "Low shelf",
+            FilterKind::HighShelf => "High shelf",
+        }
+    }
+}
+
+/// A freely placed filter on top of the graphic bands.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ParametricBand {
+    pub enabled: bool,
+    pub kind: FilterKind,
+    pub frequency: f32,
+    pub gain: f32,
+    pub q: f32,
+}
+
+impl Default for ParametricBand {
+    fn default() -> Self {
+        Self { enabled: true, kind: FilterKind::Peak, frequency: 1000.0, gain: 0.0, q: 1.0 }
+    }
+}
+
+/// Graphic band gains in dB, saved under a name.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct EqPreset {
+    pub name: String,
+    pub gains: [f32; 10],
+}
+
+impl EqPreset {
+    fn new(name: &str, gains: [f32; 10]) -> Self {
+        Self { name: name.to_string(), gains }
+    }
+
+    pub fn builtin() -> Vec<EqPreset> {
+        vec![
+            EqPreset::new("Flat", [0.0; 10]),
+            EqPreset::new("Bass Boost", [6.0, 5.0, 4.0, 2.0, 0.5, 0.0, 0.0, 0.0, 0.0, 0.0]),
+            EqPreset::new("Treble Boost", [0.0, 0.0, 0.0, 0.0, 0.0, 0.5, 2.0, 4.0, 5.0, 6.0]),
+            EqPreset::new("Vocal", [-2.0, -2.0, -1.0, 1.0, 3.0, 4.0, 3.5, 2.0, 0.0, -1.0]),
+            EqPreset::new("Rock", [4.5, 3.5, 2.0, -0.5, -1.5, -1.0, 1.0, 2.5, 3.5, 4.0]),
+            EqPreset::new("Pop", [-1.0, 0.5, 2.0, 3.5, 4.0, 3.0, 1.0, -0.5, -1.0, -1.0]),
+            EqPreset::new("Classical", [4.0, 3.0, 2.0, 1.0, -1.0, -1.0, 0.0, 2.0, 3.0, 3.5]),
+            EqPreset::new("Loudness", [5.0, 4.0, 1.5, 0.0, -1.0, -1.0, 0.0, 1.0, 3.5, 4.5]),
+        ]
+    }
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EqSettings {
+    pub enabled: bool,
+    /// Gain in dB applied before the filters, to leave headroom for boosts.
+    pub preamp: f32,
+    /// Graphic band gains in dB, one per entry of `GRAPHIC_FREQUENCIES`.
+    pub gains: [f32; 10],
+    pub parametric: Vec<ParametricBand>,
+    pub user_presets: Vec<EqPreset>,
+}
+
+impl Default for EqSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            preamp: 0.0,
+            gains: [0.0; 10],
+            parametric: Vec::new(),
+            user_presets: Vec::new(),
+        }
+    }
+}
+
+impl EqSettings {
+    /// One filter per band at `sample_rate`: the graphic bands, then the
+    /// parametric ones. Bands that don't change the sound have no coefficients.
+    fn bands(&self, sample_rate: f64) -> Vec<BandFilter> {
+        let graphic = GRAPHIC_FREQUENCIES.iter().zip(self.gains).map(|(&frequency, gain)| {
+            BandFilter::new(FilterKind::Peak, frequency, gain, OCTAVE_Q as f32, self.enabled, sample_rate)
+        });
+        let parametric = self.parametric.iter().map(|band| {
+            BandFilter::new(band.kind, band.frequency, band.gain, band.q, self.enabled && band.enabled, sample_rate)
+        });
+        graphic.chain(parametric).collect()
+    }
+
+    fn preamp_factor(&self) -> f32 {
+        if self.enabled { crate::replaygain::db_to_factor(self.preamp) } else { 1.0 }
+    }
+
+    pub fn apply_preset(&mut self, preset: &EqPreset) {
+        self.gains = preset.gains;
+        self.enabled = true;
+    }
+
+    /// Whether a built-in or user preset already goes by `name`.
+    pub fn is_preset_name_taken(&self, name: &str) -> bool {
+        EqPreset::builtin().iter().chain(&self.user_presets).any(|p| p.name == name)
+    }
+
+    /// Saves the current graphic bands as a user preset. Returns false, saving
+    /// nothing, if the name is taken.
+    pub fn save_preset(&mut self, name: &str) -> bool {
+        if self.is_preset_name_taken(name) {
+            return false;
+        }
+        self.user_presets.push(EqPreset::new(name, self.gains));
+        true
+    }
+
+    pub fn delete_preset(&mut self, name: &str) {
+        self.user_presets.retain(|p| p.name != name);
+    }
+}
+
+/// The equalizer's overall frequency response, for drawing its curve. The
+/// filters are worked out once, rather than for every point on it.
+pub struct EqResponse {
+    settings: EqSettings,
+    sample_rate: u32,
+    filters: Vec<Coefficients>,
+}
+
+impl EqResponse {
+    pub fn new(settings: &EqSettings, sample_rate: u32) -> Self {
+        let filters = settings.bands(sample_rate as f64).into_iter().filter_map(|band| band.coefficients).collect();
+        Self { settings: settings.clone(), sample_rate, filters }
+    }
+
+    /// Whether this is still the response of `settings` at `sample_rate`.
+    pub fn is_for(&self, settings: &EqSettings, sample_rate: u32) -> bool {
+        self.sample_rate == sample_rate && self.settings == *settings
+    }
+
+    /// Overall gain in dB at `frequency`, preamp included.
+    pub fn at(&self, frequency: f32) -> f32 {
+        if !self.settings.enabled {
+            return 0.0;
+        }
+        let filters_db: f64 = self
+            .filters
+            .iter()
+            .map(|filter| filter.magnitude_db(frequency as f64, self.sample_rate as f64))
+            .sum();
+        self.settings.preamp + filters_db as f32
+    }
+}
+
+/// A band's filter, with what it was worked out from so the equalizer can
+/// tell whether its memory still fits.
+#[derive(Clone, Copy)]
+struct BandFilter {
+    kind: FilterKind,
+    frequency: f32,
+    coefficients: Option<Coefficients>,
+}
+
+impl BandFilter {
+    fn new(kind: FilterKind, frequency: f32, gain: f32, q: f32, active: bool, sample_rate: f64) -> Self {
+        let coefficients = Some(Coefficients::new(kind, frequency as f64, gain as f64, q as f64, sample_rate))
+            .filter(|_| active && gain != 0.0)
+            // Anything at or above Nyquist can't be realized at this rate
+            .filter(|coefficients| coefficients.is_stable());
+        Self { kind, frequency, coefficients }
+    }
+
+    /// Whether memory built up by `other` carries over to this filter.
+    fn continues(&self, other: &BandFilter) -> bool {
+        self.kind == other.kind && self.frequency == other.frequency && other.coefficients.is_some()
+    }
+}
+
+/// Normalized biquad coefficients from the RBJ audio EQ cookbook.
+#[derive(Clone, Copy)]
+struct Coefficients {
+    b: [f64; 3],
+    a: [f64; 2],
+}
+
+impl Coefficients {
+    fn new(kind: FilterKind, frequency: f64, gain_db: f64, q: f64, sample_rate: f64) -> Self {
+        let a = 10f64.powf(gain_db / 40.0);
+        let w0 = 2.0 * PI * frequency / sample_rate;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * q.max(0.01));
+
+        let (b, a) = match kind {
+            FilterKind::Peak => (
+                [1.0 + alpha * a, -2.0 * cos, 1.0 - alpha * a],
+                [1.0 + alpha / a, -2.0 * cos, 1.0 - alpha / a],
+            ),
+            FilterKind::LowShelf => {
+                let beta = 2.0 * a.sqrt() * alpha;
+                (
+                    [
+                        a * ((a + 1.0) - (a - 1.0) * cos + beta),
+                        2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+                        a * ((a + 1.0) - (a - 1.0) * cos - beta),
+                    ],
+                    [
+                        (a + 1.0) + (a - 1.0) * cos + beta,
+                        -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+                        (a + 1.0) + (a - 1.0) * cos - beta,
+                    ],
+                )
+            }
+            FilterKind::HighShelf => {
+                let beta = 2.0 * a.sqrt() * alpha;
+                (
+                    [
+                        a * ((a + 1.0) + (a - 1.0) * cos + beta),
+                        -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+                        a * ((a + 1.0) + (a - 1.0) * cos - beta),
+                    ],
+                    [
+                        (a + 1.0) - (a - 1.0) * cos + beta,
+                        2.0 * ((a - 1.0) - (a + 1.0) * cos),
+                        (a + 1.0) - (a - 1.0) * cos - beta,
+                    ],
+                )
+            }
+        };
+
+        Self {
+            b: [b[0] / a[0], b[1] / a[0], b[2] / a[0]],
+            a: [a[1] / a[0], a[2] / a[0]],
+        }
+    }
+
+    fn is_stable(&self) -> bool {
+        self.a[1].abs() < 1.0 && self.a[0].abs() < 1.0 + self.a[1]
+    }
+
+    fn magnitude_db(&self, frequency: f64, sample_rate: f64) -> f64 {
+        // Evaluate H(z) on the unit circle at z = e^(jw)
+        let w = 2.0 * PI * frequency / sample_rate;
+        let (sin1, cos1) = w.sin_cos();
+        let (sin2, cos2) = (2.0 * w).sin_cos();
+        let num_re = self.b[0] + self.b[1] * cos1 + self.b[2] * cos2;
+        let num_im = -(self.b[1] * sin1 + self.b[2] * sin2);
+        let den_re = 1.0 + self.a[0] * cos1 + self.a[1] * cos2;
+        let den_im = -(self.a[0] * sin1 + self.a[1] * sin2);
+        let power = (num_re * num_re + num_im * num_im) / (den_re * den_re + den_im * den_im);
+        10.0 * power.log10()
+    }
+}
+
+/// Filter memory for one biquad on one channel (transposed direct form II).
+#[derive(Clone, Copy, Default)]
+struct FilterState {
+    s1: f64,
+    s2: f64,
+}
+
+impl FilterState {
+    fn process(&mut self, c: &Coefficients, input: f64) -> f64 {
+        let output = c.b[0] * input + self.s1;
+        self.s1 = c.b[1] * input - c.a[0] * output + self.s2;
+        self.s2 = c.b[2] * input - c.a[1] * output;
+        output
+    }
+}
+
+/// Runs a source through the equalizer described by shared `EqSettings`,
+/// picking up changes made from the UI while it plays.
+pub struct Equalizer<S> {
+    input: S,
+    shared: Arc<Mutex<EqSettings>>,
+    settings: EqSettings,
+    sample_rate: u32,
+    channels: usize,
+    preamp: f32,
+    bands: Vec<BandFilter>,
+    // One state per band per channel, band-major, so each band keeps its
+    // own memory as others are switched on and off
+    states: Vec<FilterState>,
+    channel: usize,
+}
+
+impl<S: Source> Equalizer<S> {
+    pub fn new(input: S, shared: Arc<Mutex<EqSettings>>) -> Self {
+        let settings = shared.lock().unwrap().clone();
+        let mut equalizer = Self {
+            sample_rate: input.sample_rate(),
+            channels: input.channels() as usize,
+            input,
+            shared,
+            settings,
+            preamp: 1.0,
+            bands: Vec::new(),
+            states: Vec::new(),
+            channel: 0,
+        };
+        equalizer.rebuild();
+        equalizer
+    }
+
+    /// Picks up new settings, keeping the filter memory so changes don't click.
+    pub fn refresh(&mut self) {
+        let settings = self.shared.lock().unwrap();
+        if *settings != self.settings {
+            self.settings = settings.clone();
+            drop(settings);
+            self.rebuild();
+        }
+    }
+
+    fn rebuild(&mut self) {
+        self.preamp = self.settings.preamp_factor();
+        let bands = self.settings.bands(self.sample_rate as f64);
+        self.states.resize(bands.len() * self.channels, FilterState::default());
+
+        // A band that's new, moved or changed type starts from silence
+        for (i, band) in bands.iter().enumerate() {
+            if !self.bands.get(i).is_some_and(|old| band.continues(old)) {
+                self.states[i * self.channels..(i + 1) * self.channels].fill(FilterState::default());
+            }
+        }
+        self.bands = bands;
+    }
+}
+
+impl<S: Source> Iterator for Equalizer<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        // The format can change between spans; only check on frame boundaries
+        if self.channel == 0
+            && (self.input.sample_rate() != self.sample_rate
+                || self.input.channels() as usize != self.channels)
+        {
+            self.sample_rate = self.input.sample_rate();
+            self.channels = self.input.channels() as usize;
+            self.states.clear();
+            self.rebuild();
+        }
+
+        let sample = self.input.next()?;
+        let channel = self.channel;
+        self.channel = (self.channel + 1) % self.channels.max(1);
+
+        let mut value = (sample * self.preamp) as f64;
+        for (i, band) in self.bands.iter().enumerate() {
+            if let Some(filter) = &band.coefficients {
+                value = self.states[i * self.channels + channel].process(filter, value);
+            }
+        }
+        Some(value as f32)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<S: Source> Source for Equalizer<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        self.input.current_span_len()
+    }
+
+    fn channels(&self) -> rodio::ChannelCount {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> rodio::SampleRate {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)?;
+        // Old filter memory belongs to audio we skipped over
+        self.states.fill(FilterState::default());
+        self.channel = 0;
+        Ok(())
+    }
+}
@@ -0,0 +1,158 @@
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use rodio::source::SeekError;
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+
+pub const MAX_FADE_MS: u64 = 500;
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FadeSettings {
+    /// Length of the fades on pause, resume, stop and track changes; 0 turns them off.
+    pub duration_ms: u64,
+}
+
+impl Default for FadeSettings {
+    fn default() -> Self {
+        Self { duration_ms: 150 }
+    }
+}
+
+impl FadeSettings {
+    pub fn duration(&self) -> Duration {
+        Duration::from_millis(self.duration_ms.min(MAX_FADE_MS))
+    }
+}
+
+/// Which way a track's `Fader` is ramping, shared with the playback thread.
+pub struct Fade {
+    audible: AtomicBool,
+    silent: AtomicBool,
+    duration_ms: AtomicU32,
+}
+
+impl Fade {
+    pub fn new(duration: Duration) -> Self {
+        Self {
+            audible: AtomicBool::new(true),
+            silent: AtomicBool::new(false),
+            duration_ms: AtomicU32::new(duration.as_millis() as u32),
+        }
+    }
+
+    pub fn set_duration(&self, duration: Duration) {
+        self.duration_ms.store(duration.as_millis() as u32, Ordering::Relaxed);
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::from_millis(self.duration_ms.load(Ordering::Relaxed) as u64)
+    }
+
+    pub fn fade_in(&self) {
+        self.audible.store(true, Ordering::Relaxed);
+        self.silent.store(false, Ordering::Relaxed);
+    }
+
+    pub fn fade_out(&self) {
+        self.audible.store(false, Ordering::Relaxed);
+    }
+
+    /// Whether a fade-out has finished, so the track can be paused or
+    /// stopped without a click.
+    pub fn is_silent(&self) -> bool {
+        self.silent.load(Ordering::Relaxed)
+    }
+}
+
+/// Ramps its source's gain up or down as `Fade` asks. Once faded out it
+/// holds at silence without reading any further, so the position stays put.
+pub struct Fader<S> {
+    input: S,
+    fade: Arc<Fade>,
+    gain: f32,
+    holding: bool,
+    // Index of the next sample within its frame; the gain moves per frame
+    channel: usize,
+}
+
+impl<S: Source> Fader<S> {
+    /// `faded_in` starts at full gain; otherwise the source fades in from silence.
+    pub fn new(input: S, fade: Arc<Fade>, faded_in: bool) -> Self {
+        let gain = if faded_in { 1.0 } else { 0.0 };
+        Self { input, fade, gain, holding: false, channel: 0 }
+    }
+
+    pub fn inner_mut(&mut self) -> &mut S {
+        &mut self.input
+    }
+
+    /// Fades out and stays silent until faded back in, for when something on
+    /// the playback thread has already taken the volume down.
+    pub fn hold_silent(&self) {
+        self.fade.fade_out();
+    }
+
+    fn step_gain(&mut self) {
+        let ramp_frames = self.fade.duration().as_secs_f32() * self.input.sample_rate() as f32;
+        let step = if ramp_frames >= 1.0 { 1.0 / ramp_frames } else { 1.0 };
+
+        if self.fade.audible.load(Ordering::Relaxed) {
+            self.gain = (self.gain + step).min(1.0);
+            self.holding = false;
+        } else {
+            self.gain = (self.gain - step).max(0.0);
+            if self.gain == 0.0 {
+                self.holding = true;
+                self.fade.silent.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+impl<S: Source> Iterator for Fader<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.channel == 0 {
+            self.step_gain();
+        }
+        let channels = (self.input.channels() as usize).max(1);
+        self.channel = (self.channel + 1) % channels;
+
+        if self.holding {
+            return Some(0.0);
+        }
+        // Squaring the ramp makes it sound more even than a straight line
+        self.input.next().map(|sample| sample * self.gain * self.gain)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<S: Source> Source for Fader<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        self.input.current_span_len()
+    }
+
+    fn channels(&self) -> rodio::ChannelCount {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> rodio::SampleRate {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)?;
+        self.channel = 0;
+        Ok(())
+    }
+}
@@ -0,0 +1,106 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use serde::{Deserialize, Serialize};
+
+/// Where a track was played from.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub enum PlaySource {
+    Library,
+    Playlist(String),
+    Queue,
+}
+
+impl PlaySource {
+    pub fn label(&self) -> String {
+        match self {
+            PlaySource::Library => "Library".to_string(),
+            PlaySource::Playlist(name) => format!("Playlist '{}'", name),
+            PlaySource::Queue => "Queue".to_string(),
+        }
+    }
+}
+
+/// One time a track was played.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub path: PathBuf,
+    pub title: String,
+    pub source: PlaySource,
+    pub started: SystemTime,
+    /// How far into the track playback got.
+    pub played: Duration,
+    /// Whether it played to the end rather than being skipped or stopped.
+    pub finished: bool,
+}
+
+/// Every track played, oldest first.
+pub struct History {
+    history_path: PathBuf,
+    entries: Vec<HistoryEntry>,
+    // The entry for the track that's playing, until it's finished
+    playing: Option<usize>,
+}
+
+impl History {
+    pub fn new() -> Self {
+        let history_path = PathBuf::from("./library/history.json");
+
+        let entries = fs::read_to_string(&history_path)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+
+        Self { history_path, entries, playing: None }
+    }
+
+    pub fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(parent) = self.history_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let json = serde_json::to_string(&self.entries)?;
+        fs::write(&self.history_path, json)?;
+
+        Ok(())
+    }
+
+    pub fn start(&mut self, path: &Path, title: &str, source: PlaySource) {
+        self.playing = Some(self.entries.len());
+        self.entries.push(HistoryEntry {
+            path: path.to_path_buf(),
+            title: title.to_string(),
+            source,
+            started: SystemTime::now(),
+            played: Duration::ZERO,
+            finished: false,
+        });
+    }
+
+    /// Records how the playing track ended. Does nothing if none is playing.
+    pub fn finish(&mut self, played: Duration, finished: bool) -> bool {
+        let Some(entry) = self.playing.take().and_then(|index| self.entries.get_mut(index)) else {
+            return false;
+        };
+        entry.played = played;
+        entry.finished = finished;
+        true
+    }
+
+    pub fn entries(&self) -> &[HistoryEntry] {
+        &self.entries
+    }
+
+    /// How many plays started between `from` and `to`.
+    pub fn count_range(&self, from: SystemTime, to: SystemTime) -> usize {
+        self.entries.iter().filter(|entry| entry.started >= from && entry.started < to).count()
+    }
+
+    /// Forgets the plays started between `from` and `to`.
+    pub fn clear_range(&mut self, from: SystemTime, to: SystemTime) {
+        let playing = self.playing.map(|index| self.entries[index].started);
+        self.entries.retain(|entry| entry.started < from || entry.started >= to);
+        // The playing track's entry may have moved or gone
+        self.playing = playing.and_then(|started| self.entries.iter().position(|entry| entry.started == started));
+    }
+}
@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+use eframe::egui::{Key, KeyboardShortcut, Modifiers};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Everything that can be done from the keyboard.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Action {
+    PlayPause,
+    SeekForward,
+    SeekBackward,
+    VolumeUp,
+    VolumeDown,
+    Next,
+    Previous,
+    Search,
+    RemoveTrack,
+    SetLoopA,
+    SetLoopB,
+    ClearLoop,
+}
+
+impl Action {
+    pub const ALL: [Action; 12] = [
+        Action::PlayPause,
+        Action::SeekForward,
+        Action::SeekBackward,
+        Action::VolumeUp,
+        Action::VolumeDown,
+        Action::Next,
+        Action::Previous,
+        Action::Search,
+        Action::RemoveTrack,
+        Action::SetLoopA,
+        Action::SetLoopB,
+        Action::ClearLoop,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Action::PlayPause => "Play / pause",
+            Action::SeekForward => "Seek forward",
+            Action::SeekBackward => "Seek backward",
+            Action::VolumeUp => "Volume up",
+            Action::VolumeDown => "Volume down",
+            Action::Next => "Next track",
+            Action::Previous => "Previous track",
+            Action::Search => "Search",
+            Action::RemoveTrack => "Remove from playlist",
+            Action::SetLoopA => "Set loop start",
+            Action::SetLoopB => "Set loop end",
+            Action::ClearLoop => "Clear loop",
+        }
+    }
+
+    fn default_binding(self) -> KeyBinding {
+        let (key, ctrl) = match self {
+            Action::PlayPause => (Key::Space, false),
+            Action::SeekForward => (Key::ArrowRight, false),
+            Action::SeekBackward => (Key::ArrowLeft, false),
+            Action::VolumeUp => (Key::ArrowUp, false),
+            Action::VolumeDown => (Key::ArrowDown, false),
+            Action::Next => (Key::N, false),
+            Action::Previous => (Key::P, false),
+            Action::Search => (Key::F, true),
+            Action::RemoveTrack => (Key::Delete, false),
+            Action::SetLoopA => (Key::OpenBracket, false),
+            Action::SetLoopB => (Key::CloseBracket, false),
+            Action::ClearLoop => (Key::Backslash, false),
+        };
+        KeyBinding { key, ctrl, shift: false, alt: false }
+    }
+}
+
+/// A key and the modifiers held with it. Ctrl stands for Cmd on a Mac.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct KeyBinding {
+    #[serde(serialize_with = "serialize_key", deserialize_with = "deserialize_key")]
+    pub key: Key,
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+}
+
+impl KeyBinding {
+    pub fn new(key: Key, modifiers: Modifiers) -> Self {
+        Self { key, ctrl: modifiers.command, shift: modifiers.shift, alt: modifiers.alt }
+    }
+
+    pub fn shortcut(&self) -> KeyboardShortcut {
+        let mut modifiers = if self.ctrl { Modifiers::COMMAND } else { Modifiers::NONE };
+        modifiers.shift = self.shift;
+        modifiers.alt = self.alt;
+        KeyboardShortcut::new(modifiers, self.key)
+    }
+
+    fn modifier_count(&self) -> usize {
+        [self.ctrl, self.shift, self.alt].into_iter().filter(|held| *held).count()
+    }
+}
+
+// Keys are stored by name, which egui can turn back into a key
+fn serialize_key<S: Serializer>(key: &Key, serializer: S) -> Result<S::Ok, S::Error> {
+    key.name().serialize(serializer)
+}
+
+fn deserialize_key<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Key, D::Error> {
+    let name = String::deserialize(deserializer)?;
+    Key::from_name(&name).ok_or_else(|| serde::de::Error::custom(format!("unknown key '{}'", name)))
+}
+
+/// Bindings the user changed from the defaults.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeyBindings {
+    custom: HashMap<Action, KeyBinding>,
+}
+
+impl KeyBindings {
+    pub fn get(&self, action: Action) -> KeyBinding {
+        self.custom.get(&action).copied().unwrap_or_else(|| action.default_binding())
+    }
+
+    pub fn set(&mut self, action: Action, binding: KeyBinding) {
+        if binding == action.default_binding() {
+            self.custom.remove(&action);
+        } else {
+            self.custom.insert(action, binding);
+        }
+    }
+
+    pub fn reset(&mut self, action: Action) {
+        self.custom.remove(&action);
+    }
+
+    pub fn reset_all(&mut self) {
+        self.custom.clear();
+    }
+
+    pub fn is_custom(&self, action: Action) -> bool {
+        self.custom.contains_key(&action)
+    }
+
+    /// Other actions bound to the same keys as `action`.
+    pub fn conflicts(&self, action: Action) -> Vec<Action> {
+        let binding = self.get(action);
+        Action::ALL
+            .into_iter()
+            .filter(|other| *other != action && self.get(*other) == binding)
+            .collect()
+    }
+
+    /// Every action with its shortcut, the ones with the most modifiers
+    /// first. egui lets a shortcut match with extra Shift or Alt held, so
+    /// this way a Shift+Right binding gets the key press before Right does.
+    pub fn shortcuts(&self) -> Vec<(Action, KeyboardShortcut)> {
+        let mut actions = Action::ALL.to_vec();
+        actions.sort_by_key(|action| std::cmp::Reverse(self.get(*action).modifier_count()));
+        actions.into_iter().map(|action| (action, self.get(action).shortcut())).collect()
+    }
+}
@@ -0,0 +1,32 @@
+// Stores and loaders open their files in `new`, so they have no sensible
+// `Default`; `Playlist::next` reads better than an `Iterator` would
+#![allow(clippy::new_without_default, clippy::should_implement_trait)]
+
+pub mod player;
+pub mod abloop;
+pub mod artwork;
+pub mod audio;
+pub mod browser;
+pub mod cli;
+pub mod ui;
+pub mod playlist;
+pub mod playlist_manager;
+pub mod queue;
+pub mod equalizer;
+pub mod fade;
+pub mod history;
+pub mod keybindings;
+pub mod library;
+pub mod loudness;
+pub mod output;
+pub mod replaygain;
+pub mod search;
+pub mod settings;
+pub mod smart_playlist;
+pub mod sleep;
+pub mod sort;
+pub mod stereo;
+pub mod timestretch;
+pub mod visualizer;
+pub mod waveform;
+pub mod worker;
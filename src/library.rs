@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use serde::{Deserialize, Serialize};
+use crate::audio::{AudioFile, DurationAccuracy};
+use crate::loudness::Loudness;
+
+/// What we've learned about a track that a plain scan can't tell us.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LibraryEntry {
+    /// Length measured by playing the track all the way through.
+    pub played_duration: Option<Duration>,
+    /// Length found by walking the frame headers, for files without one.
+    pub scanned_duration: Option<Duration>,
+    /// Result of the background EBU R128 analysis.
+    pub loudness: Option<Loudness>,
+}
+
+// A track counts as played once this much of it, or this long, has played
+const PLAYED_FRACTION: f64 = 0.5;
+const PLAYED_TIME: Duration = Duration::from_secs(4 * 60);
+
+/// Names a track by what's in the file rather than where it is, so its
+/// stats follow it when it's moved or renamed.
+#[derive(Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct TrackId(String);
+
+impl TrackId {
+    /// Hashes a track's length in frames and its audio data as demuxed,
+    /// from the start and the middle, leaving out the tags and pictures
+    /// around it so editing them keeps the id.
+    pub fn from_audio(head: &[u8], n_frames: u64, middle: &[u8]) -> Self {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(&n_frames.to_le_bytes());
+        hasher.update(head);
+        hasher.update(middle);
+        Self(hasher.finalize().to_hex().to_string())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// How a track has been listened to and rated.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrackStats {
+    pub play_count: u32,
+    /// Times it was left for another track before it counted as played.
+    pub skip_count: u32,
+    pub last_played: Option<SystemTime>,
+    /// Stars from 0 to 5.
+    pub rating: u8,
+}
+
+/// Whether `played` of a track is enough for it to count as played: half of
+/// it, or four minutes of a long one.
+pub fn counts_as_played(played: Duration, duration: Option<Duration>) -> bool {
+    played >= PLAYED_TIME || duration.is_some_and(|duration| played.as_secs_f64() >= duration.as_secs_f64() * PLAYED_FRACTION)
+}
+
+pub struct Library {
+    index_path: PathBuf,
+    entries: HashMap<PathBuf, LibraryEntry>,
+    stats_path: PathBuf,
+    stats: HashMap<TrackId, TrackStats>,
+}
+
+impl Library {
+    pub fn new() -> Self {
+        let index_path = PathBuf::from("./library/index.json");
+
+        // A missing or unreadable index just means we start from scratch
+        let entries = fs::read_to_string(&index_path)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+
+        let stats_path = PathBuf::from("./library/stats.json");
+        let stats = fs::read_to_string(&stats_path)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+
+        Self { index_path, entries, stats_path, stats }
+    }
+
+    pub fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(parent) = self.index_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let json = serde_json::to_string_pretty(&self.entries)?;
+        fs::write(&self.index_path, json)?;
+
+        let json = serde_json::to_string_pretty(&self.stats)?;
+        fs::write(&self.stats_path, json)?;
+
+        Ok(())
+    }
+
+    /// Replaces scanned values with the ones recorded in the index.
+    pub fn apply(&self, files: &mut [AudioFile]) {
+        for file in files {
+            file.stats = self.stats(file);
+
+            let Some(entry) = self.entries.get(&file.path) else { continue };
+
+            if let Some(duration) = entry.played_duration {
+                file.set_duration(duration, DurationAccuracy::Exact);
+            } else if let Some(duration) = entry.scanned_duration
+                && file.is_duration_estimated()
+            {
+                file.set_duration(duration, DurationAccuracy::Estimated);
+            }
+
+            // Measured loudness stands in for missing tags, so it goes
+            // through the same normalization as tagged files
+            if file.replay_gain.track_gain.is_none()
+                && let Some(loudness) = entry.loudness
+            {
+                file.replay_gain.track_gain = Some(loudness.replay_gain() as f32);
+                file.replay_gain.track_peak = Some(loudness.true_peak as f32);
+            }
+        }
+    }
+
+    pub fn loudness(&self, path: &Path) -> Option<&Loudness> {
+        self.entries.get(path).and_then(|entry| entry.loudness.as_ref())
+    }
+
+    pub fn record_loudness(&mut self, path: &Path, loudness: Loudness) {
+        self.entries.entry(path.to_path_buf()).or_default().loudness = Some(loudness);
+    }
+
+    pub fn record_played_duration(&mut self, path: &Path, duration: Duration) {
+        self.entries.entry(path.to_path_buf()).or_default().played_duration = Some(duration);
+    }
+
+    pub fn record_scanned_duration(&mut self, path: &Path, duration: Duration) {
+        self.entries.entry(path.to_path_buf()).or_default().scanned_duration = Some(duration);
+    }
+
+    /// Whether a file's length is still only a guess from its first few frames.
+    pub fn needs_duration_scan(&self, file: &AudioFile) -> bool {
+        file.is_duration_estimated()
+            && self.entries.get(&file.path).is_none_or(|entry| entry.played_duration.is_none() && entry.scanned_duration.is_none())
+    }
+
+    pub fn stats(&self, file: &AudioFile) -> TrackStats {
+        file.id.as_ref().map(|id| self.stats_by_id(id)).unwrap_or_default()
+    }
+
+    pub fn stats_by_id(&self, id: &TrackId) -> TrackStats {
+        self.stats.get(id).cloned().unwrap_or_default()
+    }
+
+    pub fn record_play(&mut self, id: &TrackId) {
+        let stats = self.stats.entry(id.clone()).or_default();
+        stats.play_count += 1;
+        stats.last_played = Some(SystemTime::now());
+    }
+
+    pub fn record_skip(&mut self, id: &TrackId) {
+        self.stats.entry(id.clone()).or_default().skip_count += 1;
+    }
+
+    pub fn set_rating(&mut self, id: &TrackId, rating: u8) {
+        self.stats.entry(id.clone()).or_default().rating = rating.min(5);
+    }
+}
@@ -0,0 +1,294 @@
+use std::f64::consts::PI;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use symphonia::core::audio::Channels;
+use crate::audio::decode_file;
+use crate::worker::BackgroundWorker;
+
+/// Loudness that ReplayGain 2.0 normalizes every track to.
+pub const REFERENCE_LOUDNESS: f64 = -18.0;
+
+const ABSOLUTE_GATE: f64 = -70.0;
+const INTEGRATED_RELATIVE_GATE: f64 = -10.0;
+const RANGE_RELATIVE_GATE: f64 = -20.0;
+
+// Energy is gathered in 100 ms steps; momentary blocks span 4 of them and
+// short-term windows 30.
+const STEPS_PER_BLOCK: usize = 4;
+const STEPS_PER_SHORT_TERM: usize = 30;
+
+const OVERSAMPLING: usize = 4;
+const TAPS_PER_PHASE: usize = 12;
+
+/// EBU R128 measurements for one track.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct Loudness {
+    /// Integrated loudness in LUFS.
+    pub integrated: f64,
+    /// Loudness range in LU.
+    pub range: f64,
+    /// True peak as a linear amplitude, 1.0 being full scale.
+    pub true_peak: f64,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LoudnessSettings {
+    /// Measure tracks that have no ReplayGain tags in the background.
+    pub analyze: bool,
+    /// Store finished measurements in the files as ReplayGain tags.
+    pub write_tags: bool,
+}
+
+impl Default for LoudnessSettings {
+    fn default() -> Self {
+        Self { analyze: true, write_tags: false }
+    }
+}
+
+impl Loudness {
+    /// Gain in dB that brings the track to the ReplayGain reference level.
+    pub fn replay_gain(&self) -> f64 {
+        REFERENCE_LOUDNESS - self.integrated
+    }
+}
+
+/// Decodes a whole file and measures it.
+pub fn analyze_file(path: &Path) -> Result<Loudness, Box<dyn std::error::Error>> {
+    let mut meter: Option<LoudnessMeter> = None;
+    decode_file(path, |samples, spec| {
+        meter
+            .get_or_insert_with(|| LoudnessMeter::new(spec.rate, spec.channels))
+            .add_frames(samples);
+    })?;
+
+    meter.and_then(|m| m.finish()).ok_or_else(|| "Track is too short to measure".into())
+}
+
+/// Runs loudness analysis on a worker thread so the UI never waits on decoding.
+pub type LoudnessAnalyzer = BackgroundWorker<Result<Loudness, String>>;
+
+impl LoudnessAnalyzer {
+    pub fn new() -> Self {
+        Self::spawn(|path| analyze_file(path).map_err(|e| e.to_string()))
+    }
+}
+
+/// Second-order IIR filter section (direct form I).
+#[derive(Clone)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 3],
+    x: [f64; 2],
+    y: [f64; 2],
+}
+
+impl Biquad {
+    fn new(b: [f64; 3], a: [f64; 3]) -> Self {
+        Self { b, a, x: [0.0; 2], y: [0.0; 2] }
+    }
+
+    fn process(&mut self, input: f64) -> f64 {
+        let output = self.b[0] * input + self.b[1] * self.x[0] + self.b[2] * self.x[1]
+            - self.a[1] * self.y[0]
+            - self.a[2] * self.y[1];
+        self.x = [input, self.x[0]];
+        self.y = [output, self.y[0]];
+        output
+    }
+}
+
+/// The two K-weighting stages from ITU-R BS.1770, derived for any sample rate.
+fn k_weighting(sample_rate: f64) -> [Biquad; 2] {
+    // High shelf modelling the acoustic effect of the head
+    let f0 = 1681.974450955533;
+    let gain_db = 3.999843853973347;
+    let q = 0.7071752369554196;
+    let k = (PI * f0 / sample_rate).tan();
+    let vh = 10f64.powf(gain_db / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad::new(
+        [(vh + vb * k / q + k * k) / a0, 2.0 * (k * k - vh) / a0, (vh - vb * k / q + k * k) / a0],
+        [1.0, 2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    );
+
+    // RLB high pass
+    let f0 = 38.13547087602444;
+    let q = 0.5003270373238773;
+    let k = (PI * f0 / sample_rate).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Biquad::new(
+        [1.0, -2.0, 1.0],
+        [1.0, 2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    );
+
+    [shelf, high_pass]
+}
+
+/// Windowed-sinc interpolator coefficients for 4x oversampling.
+fn oversampling_filter() -> Vec<f64> {
+    let len = OVERSAMPLING * TAPS_PER_PHASE;
+    let center = (len - 1) as f64 / 2.0;
+    (0..len)
+        .map(|n| {
+            let t = (n as f64 - center) / OVERSAMPLING as f64;
+            let sinc = if t == 0.0 { 1.0 } else { (PI * t).sin() / (PI * t) };
+            let window = 0.5 - 0.5 * (2.0 * PI * n as f64 / (len - 1) as f64).cos();
+            sinc * window
+        })
+        .collect()
+}
+
+struct ChannelState {
+    weight: f64,
+    filters: [Biquad; 2],
+    /// Most recent input samples for the true-peak interpolator, newest first.
+    history: [f64; TAPS_PER_PHASE],
+    step_energy: f64,
+}
+
+struct LoudnessMeter {
+    channels: Vec<ChannelState>,
+    oversampling_filter: Vec<f64>,
+    frames_per_step: usize,
+    frames_in_step: usize,
+    /// Channel-weighted mean square of every completed 100 ms step.
+    steps: Vec<f64>,
+    true_peak: f64,
+}
+
+impl LoudnessMeter {
+    fn new(sample_rate: u32, channels: Channels) -> Self {
+        let channels = channels
+            .iter()
+            .map(|channel| ChannelState {
+                weight: channel_weight(channel),
+                filters: k_weighting(sample_rate as f64),
+                history: [0.0; TAPS_PER_PHASE],
+                step_energy: 0.0,
+            })
+            .collect();
+
+        Self {
+            channels,
+            oversampling_filter: oversampling_filter(),
+            frames_per_step: (sample_rate as usize / 10).max(1),
+            frames_in_step: 0,
+            steps: Vec::new(),
+            true_peak: 0.0,
+        }
+    }
+
+    fn add_frames(&mut self, interleaved: &[f32]) {
+        if self.channels.is_empty() {
+            return;
+        }
+
+        for frame in interleaved.chunks_exact(self.channels.len()) {
+            for (channel, &sample) in self.channels.iter_mut().zip(frame) {
+                let sample = sample as f64;
+
+                let filtered = channel.filters.iter_mut().fold(sample, |s, f| f.process(s));
+                channel.step_energy += filtered * filtered;
+
+                channel.history.copy_within(0..TAPS_PER_PHASE - 1, 1);
+                channel.history[0] = sample;
+                for phase in 0..OVERSAMPLING {
+                    let interpolated: f64 = channel
+                        .history
+                        .iter()
+                        .enumerate()
+                        .map(|(k, x)| self.oversampling_filter[phase + OVERSAMPLING * k] * x)
+                        .sum();
+                    self.true_peak = self.true_peak.max(interpolated.abs());
+                }
+                self.true_peak = self.true_peak.max(sample.abs());
+            }
+
+            self.frames_in_step += 1;
+            if self.frames_in_step == self.frames_per_step {
+                let energy = self
+                    .channels
+                    .iter_mut()
+                    .map(|c| c.weight * std::mem::take(&mut c.step_energy))
+                    .sum::<f64>();
+                self.steps.push(energy / self.frames_per_step as f64);
+                self.frames_in_step = 0;
+            }
+        }
+    }
+
+    fn finish(self) -> Option<Loudness> {
+        let blocks = windows(&self.steps, STEPS_PER_BLOCK);
+        let short_terms = windows(&self.steps, STEPS_PER_SHORT_TERM);
+
+        let integrated = energy_to_lufs(mean(&gate(&blocks, INTEGRATED_RELATIVE_GATE))?);
+
+        // Loudness range is the spread between the 10th and 95th percentile
+        // of the gated short-term loudness.
+        let mut gated: Vec<f64> = gate(&short_terms, RANGE_RELATIVE_GATE)
+            .into_iter()
+            .map(energy_to_lufs)
+            .collect();
+        gated.sort_by(|a, b| a.total_cmp(b));
+        let range = if gated.is_empty() {
+            0.0
+        } else {
+            let percentile = |p: f64| gated[((gated.len() - 1) as f64 * p).round() as usize];
+            percentile(0.95) - percentile(0.10)
+        };
+
+        Some(Loudness { integrated, range, true_peak: self.true_peak })
+    }
+}
+
+fn channel_weight(channel: Channels) -> f64 {
+    if channel == Channels::LFE1 || channel == Channels::LFE2 {
+        0.0
+    } else if channel == Channels::SIDE_LEFT
+        || channel == Channels::SIDE_RIGHT
+        || channel == Channels::REAR_LEFT
+        || channel == Channels::REAR_RIGHT
+    {
+        1.41
+    } else {
+        1.0
+    }
+}
+
+/// Mean energy of each run of `len` consecutive steps, advancing one step at a time.
+fn windows(steps: &[f64], len: usize) -> Vec<f64> {
+    steps.windows(len).map(|w| w.iter().sum::<f64>() / len as f64).collect()
+}
+
+/// Keeps the windows that pass the absolute gate and then the relative gate,
+/// which sits `relative_gate` LU below the mean of the absolute-gated ones.
+fn gate(windows: &[f64], relative_gate: f64) -> Vec<f64> {
+    let above_absolute: Vec<f64> = windows
+        .iter()
+        .copied()
+        .filter(|&e| energy_to_lufs(e) > ABSOLUTE_GATE)
+        .collect();
+    let Some(mean) = mean(&above_absolute) else {
+        return Vec::new();
+    };
+
+    let threshold = energy_to_lufs(mean) + relative_gate;
+    above_absolute
+        .into_iter()
+        .filter(|&e| energy_to_lufs(e) > threshold)
+        .collect()
+}
+
+fn mean(values: &[f64]) -> Option<f64> {
+    if values.is_empty() {
+        None
+    } else {
+        Some(values.iter().sum::<f64>() / values.len() as f64)
+    }
+}
+
+fn energy_to_lufs(energy: f64) -> f64 {
+    -0.691 + 10.0 * energy.log10()
+}
@@ -1,15 +1,12 @@
-mod player;
-mod audio;
-mod ui;
-mod playlist;
-mod playlist_manager;
-
+use clap::Parser;
 use eframe::egui;
+use rust_audio_player::{cli, ui};
 //use player::Player;
 //use audio::find_audio_files;
 //use std::path::PathBuf;
 
 fn main() -> Result<(), eframe::Error> {
+    let args = cli::Args::parse();
 
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
@@ -20,6 +17,6 @@ fn main() -> Result<(), eframe::Error> {
     eframe::run_native(
         "Lil Glucose",
         options, 
-        Box::new(|_cc| Ok(Box::new(ui::AudioPlayerApp::default()))),
+        Box::new(|_cc| Ok(Box::new(ui::AudioPlayerApp::new(args)))),
     )
 }
\ No newline at end of file
@@ -0,0 +1,262 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use rodio::cpal::traits::{DeviceTrait, HostTrait};
+use rodio::mixer::Mixer;
+use rodio::OutputStream;
+use serde::{Deserialize, Serialize};
+use crate::player::PlayerError;
+
+// Format used by the outputs that don't have a sound card to ask
+const HEADLESS_CHANNELS: u16 = 2;
+const HEADLESS_SAMPLE_RATE: u32 = 44_100;
+// Headless outputs pull audio in chunks of this length
+const HEADLESS_CHUNK: Duration = Duration::from_millis(10);
+const WAV_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Where the player sends its audio.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub enum OutputTarget {
+    /// A sound card by name, `None` being the system default.
+    Device(Option<String>),
+    /// Record everything that's played into a WAV file.
+    WavFile(PathBuf),
+    /// Throw the audio away, consuming it at `speed` times real time.
+    Null { speed: f32 },
+}
+
+impl Default for OutputTarget {
+    fn default() -> Self {
+        OutputTarget::Device(None)
+    }
+}
+
+impl OutputTarget {
+    /// One target of each kind, with default options, for pickers.
+    pub fn kinds() -> [OutputTarget; 3] {
+        [
+            OutputTarget::Device(None),
+            OutputTarget::WavFile(PathBuf::from("./output.wav")),
+            OutputTarget::Null { speed: 1.0 },
+        ]
+    }
+
+    pub fn kind_label(&self) -> &'static str {
+        match self {
+            OutputTarget::Device(_) => "Audio device",
+            OutputTarget::WavFile(_) => "WAV file",
+            OutputTarget::Null { .. } => "Null (discard)",
+        }
+    }
+
+    pub fn is_same_kind(&self, other: &OutputTarget) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+
+    pub fn open(&self) -> Result<Box<dyn AudioOutput>, PlayerError> {
+        Ok(match self {
+            OutputTarget::Device(name) => Box::new(DeviceOutput::open(name.as_deref())?),
+            OutputTarget::WavFile(path) => Box::new(WavOutput::create(path)?),
+            OutputTarget::Null { speed } => Box::new(NullOutput::new(*speed)),
+        })
+    }
+}
+
+/// Something that plays whatever is fed into its mixer.
+pub trait AudioOutput {
+    fn mixer(&self) -> &Mixer;
+
+    /// Whether the output has stopped working for good, e.g. an unplugged device.
+    fn is_lost(&self) -> bool {
+        false
+    }
+
+    /// The latest problem the output ran into but got past, e.g. falling
+    /// back to the default device. Each one is only handed out once.
+    fn take_warning(&self) -> Option<PlayerError> {
+        None
+    }
+}
+
+/// Names of the output devices currently available on the default host.
+pub fn output_devices() -> Result<Vec<String>, PlayerError> {
+    let devices = rodio::cpal::default_host().output_devices()?;
+    Ok(devices.filter_map(|device| device.name().ok()).collect())
+}
+
+/// A real sound card, through cpal.
+pub struct DeviceOutput {
+    stream: OutputStream,
+    device_lost: Arc<AtomicBool>,
+    warning: Arc<Mutex<Option<PlayerError>>>,
+}
+
+impl DeviceOutput {
+    /// Opens the named device, or the default one when `device_name` is
+    /// `None` or the device can't be used.
+    pub fn open(device_name: Option<&str>) -> Result<Self, PlayerError> {
+        let device_lost = Arc::new(AtomicBool::new(false));
+        let warning = Arc::new(Mutex::new(None));
+        let on_error = {
+            let device_lost = device_lost.clone();
+            let warning = warning.clone();
+            move |err: rodio::cpal::StreamError| match err {
+                rodio::cpal::StreamError::DeviceNotAvailable => device_lost.store(true, Ordering::SeqCst),
+                err => *warning.lock().unwrap() = Some(PlayerError::Device(format!("stream error: {}", err))),
+            }
+        };
+
+        let device = device_name.and_then(|name| {
+            rodio::cpal::default_host()
+                .output_devices()
+                .ok()?
+                .find(|device| device.name().is_ok_and(|n| n == name))
+        });
+
+        let named_stream = device.map(|device| {
+            rodio::OutputStreamBuilder::from_device(device)
+                .and_then(|builder| builder.with_error_callback(on_error.clone()).open_stream_or_fallback())
+        });
+        let mut stream = match named_stream {
+            Some(Ok(stream)) => stream,
+            named_stream => {
+                if let Some(Err(e)) = named_stream {
+                    let message = format!("can't open the chosen device, using the default: {}", e);
+                    *warning.lock().unwrap() = Some(PlayerError::Device(message));
+                }
+                rodio::OutputStreamBuilder::from_default_device()?
+                    .with_error_callback(on_error)
+                    .open_stream_or_fallback()?
+            }
+        };
+
+        // Streams get swapped out when changing devices; that's not worth a log line
+        stream.log_on_drop(false);
+        Ok(Self { stream, device_lost, warning })
+    }
+}
+
+impl AudioOutput for DeviceOutput {
+    fn mixer(&self) -> &Mixer {
+        self.stream.mixer()
+    }
+
+    fn is_lost(&self) -> bool {
+        self.device_lost.load(Ordering::SeqCst)
+    }
+
+    fn take_warning(&self) -> Option<PlayerError> {
+        self.warning.lock().unwrap().take()
+    }
+}
+
+/// Pulls audio out of a mixer on its own thread, standing in for the
+/// callback a sound card would drive.
+struct HeadlessPump {
+    mixer: Mixer,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl HeadlessPump {
+    /// Runs `write` on every chunk of samples, paced at `speed` times real time.
+    fn start(speed: f32, mut write: impl FnMut(&[f32]) + Send + 'static) -> Self {
+        let (mixer, mut source) = rodio::mixer::mixer(HEADLESS_CHANNELS, HEADLESS_SAMPLE_RATE);
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let thread = {
+            let stop = stop.clone();
+            thread::spawn(move || {
+                let chunk_len = (HEADLESS_SAMPLE_RATE as f64 * HEADLESS_CHUNK.as_secs_f64()) as usize
+                    * HEADLESS_CHANNELS as usize;
+                let chunk_time = HEADLESS_CHUNK.div_f32(speed.max(f32::EPSILON));
+                let mut chunk = Vec::with_capacity(chunk_len);
+                let mut deadline = Instant::now();
+
+                while !stop.load(Ordering::SeqCst) {
+                    // A mixer plays silence when it has nothing else, so it never runs dry
+                    chunk.clear();
+                    chunk.extend(source.by_ref().take(chunk_len));
+                    write(&chunk);
+
+                    deadline += chunk_time;
+                    if let Some(wait) = deadline.checked_duration_since(Instant::now()) {
+                        thread::sleep(wait);
+                    }
+                }
+            })
+        };
+
+        Self { mixer, stop, thread: Some(thread) }
+    }
+}
+
+impl Drop for HeadlessPump {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Discards everything, for running without a sound card.
+pub struct NullOutput {
+    pump: HeadlessPump,
+}
+
+impl NullOutput {
+    pub fn new(speed: f32) -> Self {
+        Self { pump: HeadlessPump::start(speed, |_| {}) }
+    }
+}
+
+impl AudioOutput for NullOutput {
+    fn mixer(&self) -> &Mixer {
+        &self.pump.mixer
+    }
+}
+
+/// Records the output, silence included, into a 32-bit float WAV file.
+pub struct WavOutput {
+    pump: HeadlessPump,
+}
+
+impl WavOutput {
+    pub fn create(path: &Path) -> Result<Self, PlayerError> {
+        let spec = hound::WavSpec {
+            channels: HEADLESS_CHANNELS,
+            sample_rate: HEADLESS_SAMPLE_RATE,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+        let mut writer = hound::WavWriter::create(path, spec).map_err(|e| match e {
+            hound::Error::IoError(e) => PlayerError::Io(e),
+            e => PlayerError::Device(e.to_string()),
+        })?;
+
+        let mut last_flush = Instant::now();
+        let pump = HeadlessPump::start(1.0, move |samples| {
+            for &sample in samples {
+                if writer.write_sample(sample).is_err() {
+                    return;
+                }
+            }
+            // Keep the header current so the file is usable while recording
+            if last_flush.elapsed() >= WAV_FLUSH_INTERVAL {
+                let _ = writer.flush();
+                last_flush = Instant::now();
+            }
+        });
+
+        Ok(Self { pump })
+    }
+}
+
+impl AudioOutput for WavOutput {
+    fn mixer(&self) -> &Mixer {
+        &self.pump.mixer
+    }
+}
@@ -1,80 +1,389 @@
-use rodio::{OutputStream, Sink, Decoder};
+use rodio::{Sink, Decoder, Source};
+use std::fmt;
 use std::fs::File;
 use std::io::BufReader;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
+use crate::abloop::{AbLoop, LoopState, Looper};
+use crate::equalizer::{EqSettings, Equalizer};
+use crate::fade::{Fade, Fader};
+use crate::output::{AudioOutput, OutputTarget};
+use crate::stereo::{StereoMix, StereoSettings};
+use crate::timestretch::{SpeedSettings, TimeStretch};
+use crate::visualizer::{SampleTap, TapControl, TAP_CAPACITY};
+
+// How often the playback thread picks up gain and DSP changes made from the UI
+const GAIN_UPDATE_PERIOD: Duration = Duration::from_millis(50);
+
+/// Everything that can go wrong between a file on disk and the speakers.
+#[derive(Debug)]
+pub enum PlayerError {
+    /// No output device could be listed, opened or kept running.
+    Device(String),
+    /// The file couldn't be read.
+    Io(std::io::Error),
+    /// The file isn't in a format we can decode.
+    UnsupportedFormat,
+    /// The format is known but the contents couldn't be decoded or seeked.
+    Decode(String),
+}
+
+impl fmt::Display for PlayerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlayerError::Device(msg) => write!(f, "audio device error: {}", msg),
+            PlayerError::Io(e) => write!(f, "could not read file: {}", e),
+            PlayerError::UnsupportedFormat => write!(f, "unsupported audio format"),
+            PlayerError::Decode(msg) => write!(f, "could not decode audio: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for PlayerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PlayerError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<rodio::StreamError> for PlayerError {
+    fn from(e: rodio::StreamError) -> Self {
+        PlayerError::Device(e.to_string())
+    }
+}
+
+impl From<rodio::DevicesError> for PlayerError {
+    fn from(e: rodio::DevicesError) -> Self {
+        PlayerError::Device(e.to_string())
+    }
+}
+
+impl From<std::io::Error> for PlayerError {
+    fn from(e: std::io::Error) -> Self {
+        PlayerError::Io(e)
+    }
+}
+
+impl From<rodio::decoder::DecoderError> for PlayerError {
+    fn from(e: rodio::decoder::DecoderError) -> Self {
+        match e {
+            rodio::decoder::DecoderError::UnrecognizedFormat => PlayerError::UnsupportedFormat,
+            e => PlayerError::Decode(e.to_string()),
+        }
+    }
+}
+
+impl From<rodio::source::SeekError> for PlayerError {
+    fn from(e: rodio::source::SeekError) -> Self {
+        PlayerError::Decode(e.to_string())
+    }
+}
+
+// Something to do once the current track has faded out
+enum Pending {
+    Pause,
+    Stop,
+    /// The next track, and the fade of the one it replaces.
+    Play(Box<dyn Source + Send>, Arc<Fade>),
+}
 
 pub struct Player {
-    _stream: OutputStream,
+    // Declared before `output` so the sink lets go of the mixer first
     sink: Sink,
-    playback_start: Option<Instant>,
-    pause_offset: Duration,
-    paused_at: Option<Instant>,
+    output: Box<dyn AudioOutput>,
+    current_path: Option<PathBuf>,
+    gain: Arc<Mutex<f32>>,
+    equalizer: Arc<Mutex<EqSettings>>,
+    stereo: Arc<Mutex<StereoSettings>>,
+    speed: Arc<Mutex<SpeedSettings>>,
+    ab_loop: Arc<Mutex<LoopState>>,
+    // Seconds into the current track as f64 bits, kept up by its `TimeStretch`
+    position: Arc<AtomicU64>,
+    fade: Arc<Fade>,
+    pending: Option<Pending>,
+    sleep_deadline: Arc<Mutex<Option<Instant>>>,
+    // Reading end of the current track's `SampleTap`
+    tap: Option<rtrb::Consumer<f32>>,
+    tap_control: Arc<TapControl>,
 }
 
 impl Player {
-    pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
-        let stream = rodio::OutputStreamBuilder::open_default_stream()
-            .expect("open default audio stream");
-        let sink = Sink::connect_new(stream.mixer());
-        Ok(Player {
-            _stream: stream,
+    pub fn new(target: &OutputTarget) -> Result<Self, PlayerError> {
+        Ok(Self::with_output(target.open()?))
+    }
+
+    pub fn with_output(output: Box<dyn AudioOutput>) -> Self {
+        let sink = Sink::connect_new(output.mixer());
+        Player {
             sink,
-            playback_start: None,        
-            pause_offset: Duration::ZERO,  
-            paused_at: None,   
-        })
+            output,
+            current_path: None,
+            gain: Arc::new(Mutex::new(1.0)),
+            equalizer: Arc::new(Mutex::new(EqSettings::default())),
+            stereo: Arc::new(Mutex::new(StereoSettings::default())),
+            speed: Arc::new(Mutex::new(SpeedSettings::default())),
+            ab_loop: Arc::new(Mutex::new(LoopState::default())),
+            position: Arc::new(AtomicU64::new(0)),
+            fade: Arc::new(Fade::new(Duration::ZERO)),
+            pending: None,
+            sleep_deadline: Arc::new(Mutex::new(None)),
+            tap: None,
+            tap_control: Arc::new(TapControl::new()),
+        }
     }
 
-    pub fn play(&mut self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
-        self.sink.stop();
-        
+    /// Plays a file, amplified by `gain` (a linear factor, e.g. from ReplayGain).
+    /// A track that is still playing fades out first and the new one fades in.
+    pub fn play(&mut self, path: &Path, gain: f32) -> Result<(), PlayerError> {
+        if let Some(pending) = self.pending.take() {
+            self.complete(pending);
+        }
+
+        // A track that ended on its own leaves nothing to fade
+        let interrupting = !self.sink.empty();
+        let previous_fade = self.fade.clone();
+        let source = self.open_file(path, gain, !interrupting)?;
+        self.set_loop(None);
+        self.current_path = Some(path.to_path_buf());
+
+        if interrupting && !self.sink.is_paused() && !previous_fade.duration().is_zero() {
+            previous_fade.fade_out();
+            self.pending = Some(Pending::Play(source, previous_fade));
+        } else {
+            self.complete(Pending::Play(source, previous_fade));
+        }
+
+        Ok(())
+    }
+
+    fn open_file(&mut self, path: &Path, gain: f32, faded_in: bool) -> Result<Box<dyn Source + Send>, PlayerError> {
         let file = File::open(path)?;
-        let source = Decoder::new(BufReader::new(file))?;
-        self.sink.append(source);
-        self.playback_start = Some(Instant::now());
-        self.pause_offset = Duration::ZERO;
-        self.paused_at = None;
+        let decoder = Decoder::new(BufReader::new(file))?;
+
+        // Fresh shared state, so a track that's on its way out can't touch the new one's
+        self.position = Arc::new(AtomicU64::new(0));
+        self.gain = Arc::new(Mutex::new(gain));
+        self.fade = Arc::new(Fade::new(self.fade.duration()));
+
+        let shared_gain = self.gain.clone();
+        let sleep_deadline = self.sleep_deadline.clone();
+        let equalizer = Equalizer::new(decoder, self.equalizer.clone());
+        let stereo = StereoMix::new(equalizer, self.stereo.clone());
+        let stretch = TimeStretch::new(stereo, self.speed.clone(), self.position.clone());
+        let looper = Looper::new(stretch, self.ab_loop.clone(), self.position.clone());
+        let (producer, consumer) = rtrb::RingBuffer::new(TAP_CAPACITY);
+        self.tap = Some(consumer);
+        let amplify = Fader::new(looper, self.fade.clone(), faded_in).amplify(gain);
+        let source = SampleTap::new(amplify, producer, self.tap_control.clone())
+            .periodic_access(GAIN_UPDATE_PERIOD, move |tap| {
+                let amplify = tap.inner_mut();
+                // Worked out here so the sleep fade goes on while the UI isn't drawing
+                let sleep_factor = crate::sleep::fade_factor(*sleep_deadline.lock().unwrap());
+                amplify.set_factor(*shared_gain.lock().unwrap() * sleep_factor);
+                if sleep_factor == 0.0 {
+                    amplify.inner_mut().hold_silent();
+                }
+                let looper = amplify.inner_mut().inner_mut();
+                looper.refresh();
+                let stretch = looper.inner_mut();
+                stretch.refresh();
+                let stereo = stretch.inner_mut();
+                stereo.refresh();
+                stereo.inner_mut().refresh();
+            });
+        Ok(Box::new(source))
+    }
+
+    /// Finishes a pause, stop or track change once the fade-out ahead of it
+    /// is done. Call this regularly.
+    pub fn update(&mut self) {
+        let faded = match &self.pending {
+            None => return,
+            Some(Pending::Play(_, previous_fade)) => previous_fade.is_silent(),
+            Some(_) => self.fade.is_silent(),
+        };
+        if (faded || self.sink.empty())
+            && let Some(pending) = self.pending.take()
+        {
+            self.complete(pending);
+        }
+    }
+
+    fn complete(&mut self, pending: Pending) {
+        match pending {
+            Pending::Pause => self.sink.pause(),
+            Pending::Stop => self.stop_now(),
+            Pending::Play(source, _) => {
+                self.sink.stop();
+                self.sink.append(source);
+                self.sink.play();
+            }
+        }
+    }
+
+    /// Fades playback out over the last minute before `deadline` and holds it
+    /// at silence from then on, until resumed.
+    pub fn set_sleep_deadline(&self, deadline: Option<Instant>) {
+        *self.sleep_deadline.lock().unwrap() = deadline;
+    }
+
+    /// Fade length for pause, resume, stop and track changes; zero turns fades off.
+    pub fn set_fade_duration(&self, duration: Duration) {
+        self.fade.set_duration(duration);
+    }
+
+    /// Moves playback to another output, picking up the current track where
+    /// it left off.
+    pub fn set_output(&mut self, target: &OutputTarget) -> Result<(), PlayerError> {
+        if let Some(pending) = self.pending.take() {
+            self.complete(pending);
+        }
+        let position = self.get_position();
+        let paused = self.sink.is_paused();
+        let was_playing = !self.sink.empty();
+
+        let output = target.open()?;
+        let sink = Sink::connect_new(output.mixer());
+        sink.set_volume(self.sink.volume());
+
+        self.sink.stop();
+        self.sink = sink;
+        self.output = output;
+
+        if was_playing && let Some(path) = self.current_path.clone() {
+            let gain = *self.gain.lock().unwrap();
+            let source = self.open_file(&path, gain, true)?;
+            self.sink.append(source);
+            if paused {
+                self.sink.pause();
+            }
+            self.sink.try_seek(position)?;
+        }
 
         Ok(())
     }
 
+    /// Whether the output has stopped working since the player was created
+    /// or last moved to another output.
+    pub fn is_output_lost(&self) -> bool {
+        self.output.is_lost()
+    }
+
+    /// The latest problem the output got past, for showing to the user.
+    pub fn take_output_warning(&self) -> Option<PlayerError> {
+        self.output.take_warning()
+    }
+
     pub fn pause(&mut self) {
-        self.sink.pause();
-        self.paused_at = Some(Instant::now())
+        if let Some(pending) = self.pending.take() {
+            self.complete(pending);
+        }
+        self.fade.fade_out();
+        if self.sink.empty() || self.fade.duration().is_zero() {
+            self.sink.pause();
+        } else {
+            self.pending = Some(Pending::Pause);
+        }
     }
 
     pub fn resume(&mut self) {
-        self.sink.play();
-        if let Some(paused_at) = self.paused_at.take() {
-            let pause_duration = paused_at.elapsed();
-            self.pause_offset += pause_duration;
+        if matches!(self.pending, Some(Pending::Pause)) {
+            self.pending = None;
         }
+        self.sink.play();
+        self.fade.fade_in();
     }
 
     pub fn stop(&mut self) {
+        if let Some(pending) = self.pending.take() {
+            self.complete(pending);
+        }
+        if self.sink.empty() || self.sink.is_paused() || self.fade.duration().is_zero() {
+            self.stop_now();
+        } else {
+            self.fade.fade_out();
+            self.pending = Some(Pending::Stop);
+        }
+    }
+
+    fn stop_now(&mut self) {
         self.sink.stop();
-        self.playback_start = None;
-        self.pause_offset = Duration::ZERO;
-        self.paused_at = None;
+        self.current_path = None;
+        self.position = Arc::new(AtomicU64::new(0));
     }
 
+    /// Jumps to `position` in the current track.
+    pub fn seek(&mut self, position: Duration) -> Result<(), PlayerError> {
+        if let Some(pending) = self.pending.take() {
+            self.complete(pending);
+        }
+        self.sink.try_seek(position)?;
+        Ok(())
+    }
+
+    /// Position in the current track in the track's own time, whatever the
+    /// playback speed.
     pub fn get_position(&self) -> Duration {
-        match self.playback_start {
-            Some(start) => {
-                let elapsed = start.elapsed();
-                
-                // If currently paused, calculate position up to pause time
-                if let Some(paused_at) = self.paused_at {
-                    let position = paused_at.duration_since(start);
-                    position.saturating_sub(self.pause_offset)
-                } else {
-                    // Currently playing
-                    elapsed.saturating_sub(self.pause_offset)
-                }
-            }
-            None => Duration::ZERO,
+        Duration::from_secs_f64(f64::from_bits(self.position.load(Ordering::Relaxed)))
+    }
+
+    /// Changes the gain of the track that is currently playing.
+    pub fn set_gain(&self, gain: f32) {
+        *self.gain.lock().unwrap() = gain;
+    }
+
+    /// Changes the equalizer, including for the track that is currently playing.
+    pub fn set_equalizer(&self, settings: &EqSettings) {
+        *self.equalizer.lock().unwrap() = settings.clone();
+    }
+
+    /// Changes balance and channel mixing, including for the track that is
+    /// currently playing.
+    pub fn set_stereo(&self, settings: &StereoSettings) {
+        *self.stereo.lock().unwrap() = *settings;
+    }
+
+    /// Changes speed and pitch, including for the track that is currently playing.
+    pub fn set_speed(&self, settings: &SpeedSettings) {
+        *self.speed.lock().unwrap() = *settings;
+    }
+
+    /// Sets or clears the A-B loop in the current track. Starting a new
+    /// track clears it.
+    pub fn set_loop(&self, points: Option<AbLoop>) {
+        *self.ab_loop.lock().unwrap() = LoopState { points, passes: 0, failed: false };
+    }
+
+    /// Whether the loop was dropped since the last call because the track
+    /// can't seek back to A.
+    pub fn take_loop_failure(&self) -> bool {
+        std::mem::take(&mut self.ab_loop.lock().unwrap().failed)
+    }
+
+    /// How many times the current loop has jumped back to A.
+    pub fn loop_passes(&self) -> u32 {
+        self.ab_loop.lock().unwrap().passes
+    }
+
+    /// Moves the samples played since the last call into `samples`, mixed
+    /// down to mono, and returns their sample rate.
+    pub fn drain_tap(&mut self, samples: &mut Vec<f32>) -> u32 {
+        if let Some(tap) = &mut self.tap
+            && let Ok(chunk) = tap.read_chunk(tap.slots())
+        {
+            samples.extend(chunk);
         }
+        self.tap_control.sample_rate.load(Ordering::Relaxed)
+    }
+
+    /// Turns the sample tap on or off; off saves the copying when nothing
+    /// is being visualized.
+    pub fn set_tap_enabled(&self, enabled: bool) {
+        self.tap_control.enabled.store(enabled, Ordering::Relaxed);
     }
 
     pub fn set_volume(&self, volume: f32) {
@@ -85,8 +394,10 @@ impl Player {
         self.sink.empty()
     }
 
-    pub fn wait_until_end(&self) {
-        self.sink.sleep_until_end();
+    /// Whether the current track has played out on its own, rather than
+    /// being stopped or still fading into the next one.
+    pub fn track_finished(&self) -> bool {
+        self.current_path.is_some() && self.pending.is_none() && self.sink.empty()
     }
 
 }
@@ -1,22 +1,57 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::path::{Path, PathBuf};
 use crate::audio::AudioFile;
+use crate::sort::{sort_files, SortKey};
 
 pub struct Playlist {
     files: Vec<AudioFile>,
     current_index: Option<usize>,
+    shuffle: bool,
+    // Tracks that were current before, most recent last, so going back
+    // retraces what was actually played even when shuffling
+    history: Vec<PathBuf>,
+    // Tracks gone back from, most recent last, so going forward again
+    // returns to them before anything new
+    forward: Vec<PathBuf>,
+    // Tracks not yet played in this pass through the shuffled playlist
+    unplayed: Vec<PathBuf>,
 }
 
 impl Playlist {
     pub fn new(files: Vec<AudioFile>) -> Self {
         let current_index = if files.is_empty() { None } else { Some(0) };
-        Self { files, current_index }
+        Self { files, current_index, shuffle: false, history: Vec::new(), forward: Vec::new(), unplayed: Vec::new() }
     }
 
     pub fn current(&self) -> Option<&AudioFile> {
         self.current_index.and_then(|i| self.files.get(i))
     }
 
+    pub fn current_index(&self) -> Option<usize> {
+        self.current_index
+    }
+
+    pub fn current_mut(&mut self) -> Option<&mut AudioFile> {
+        self.current_index.and_then(|i| self.files.get_mut(i))
+    }
+
     pub fn next(&mut self) -> Option<&AudioFile> {
+        self.remember_current();
+        // Tracks removed since are skipped over
+        while let Some(path) = self.forward.pop() {
+            if let Some(index) = self.position_of(&path) {
+                self.current_index = Some(index);
+                return self.files.get(index);
+            }
+        }
         if let Some(idx) = self.current_index {
+            if self.shuffle && self.files.len() > 1 {
+                let next_idx = self.next_unplayed(idx);
+                self.current_index = Some(next_idx);
+                return self.files.get(next_idx);
+            }
+
             let next_idx = idx + 1;
             if next_idx < self.files.len() {
                 self.current_index = Some(next_idx);
@@ -29,18 +64,162 @@ impl Playlist {
         None
     }
     
+    // Picks a random track that hasn't played yet this pass, starting a new
+    // pass once they all have
+    fn next_unplayed(&mut self, current: usize) -> usize {
+        loop {
+            if self.unplayed.is_empty() {
+                self.deal_unplayed(current);
+            }
+            let path = self.unplayed.swap_remove(random_below(self.unplayed.len()));
+            if let Some(index) = self.files.iter().position(|file| file.path == path) {
+                return index;
+            }
+        }
+    }
+
+    fn deal_unplayed(&mut self, current: usize) {
+        self.unplayed = self
+            .files
+            .iter()
+            .enumerate()
+            .filter(|&(index, _)| index != current)
+            .map(|(_, file)| file.path.clone())
+            .collect();
+    }
+
+    /// Whether the current track is the last to play before the playlist
+    /// starts over: the bottom one, or when shuffling, the last one not yet
+    /// played this pass.
+    pub fn is_last(&self) -> bool {
+        match self.current_index {
+            Some(_) if self.shuffle => self.unplayed.is_empty(),
+            Some(index) => index + 1 >= self.files.len(),
+            None => true,
+        }
+    }
+
+    /// Tracks still to play after the current one before the playlist starts
+    /// over, in no particular order when shuffling.
+    pub fn upcoming(&self) -> Vec<&AudioFile> {
+        match self.current_index {
+            Some(_) if self.shuffle => {
+                self.files.iter().filter(|file| self.unplayed.contains(&file.path)).collect()
+            }
+            Some(index) => self.files[index + 1..].iter().collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Goes back to the track that was current before this one, or to the
+    /// one above it once there's no history left.
     pub fn previous(&mut self) -> Option<&AudioFile> {
-        if let Some(idx) = self.current_index {
-            if idx > 0 {
-                let prev_idx = idx - 1;
-                self.current_index = Some(prev_idx);
-                return self.files.get(prev_idx);
-            } else {
-                self.current_index = Some(0);
-                return self.first();
+        let current = self.current_index?;
+
+        // Tracks removed since they played are skipped over
+        let mut target = None;
+        while let Some(path) = self.history.pop() {
+            target = self.position_of(&path);
+            if target.is_some() {
+                break;
             }
         }
-        None
+        let target = target.unwrap_or(current.saturating_sub(1));
+
+        // Going forward again should come back here
+        if target != current {
+            let path = self.files[current].path.clone();
+            self.forward.push(path);
+        }
+        self.current_index = Some(target);
+        self.files.get(target)
+    }
+
+    pub fn select(&mut self, index: usize) -> Option<&AudioFile> {
+        if index >= self.files.len() {
+            return None;
+        }
+        if self.current_index != Some(index) {
+            self.remember_current();
+            // Picking a track starts a new path through the playlist
+            self.forward.clear();
+        }
+        self.current_index = Some(index);
+        let path = &self.files[index].path;
+        self.unplayed.retain(|unplayed| unplayed != path);
+        self.files.get(index)
+    }
+
+    fn position_of(&self, path: &Path) -> Option<usize> {
+        self.files.iter().position(|file| file.path == path)
+    }
+
+    fn remember_current(&mut self) {
+        if let Some(file) = self.current() {
+            let path = file.path.clone();
+            self.history.push(path);
+        }
+    }
+
+    /// Adds tracks to the end, making the first one current if nothing was.
+    pub fn append(&mut self, files: Vec<AudioFile>) {
+        if self.current_index.is_none() && !files.is_empty() {
+            self.current_index = Some(self.files.len());
+        }
+        if self.shuffle {
+            self.unplayed.extend(files.iter().map(|file| file.path.clone()));
+        }
+        self.files.extend(files);
+    }
+
+    /// Takes a track out of the playlist. Removing the current track makes
+    /// the one after it current, or the one before if it was the last.
+    pub fn remove(&mut self, index: usize) -> Option<AudioFile> {
+        if index >= self.files.len() {
+            return None;
+        }
+        let file = self.files.remove(index);
+        self.unplayed.retain(|path| *path != file.path);
+        self.current_index = match self.current_index {
+            _ if self.files.is_empty() => None,
+            Some(current) if current > index => Some(current - 1),
+            Some(current) => Some(current.min(self.files.len() - 1)),
+            None => None,
+        };
+        Some(file)
+    }
+
+    /// Puts the tracks in order by `keys`, keeping the current track current.
+    pub fn sort(&mut self, keys: &[SortKey]) {
+        let current = self.current().map(|file| file.path.clone());
+        sort_files(&mut self.files, keys);
+        if let Some(current) = current {
+            self.current_index = self.files.iter().position(|file| file.path == current);
+        }
+    }
+
+    pub fn is_shuffled(&self) -> bool {
+        self.shuffle
+    }
+
+    /// Turning shuffle on starts a fresh pass through the tracks.
+    pub fn set_shuffle(&mut self, shuffle: bool) {
+        self.shuffle = shuffle;
+        self.unplayed.clear();
+        if shuffle {
+            self.deal_unplayed(self.current_index.unwrap_or(usize::MAX));
+        }
+    }
+
+    /// Turns shuffle on and starts the pass on a random track, as if it had
+    /// been the first one picked.
+    pub fn start_shuffled(&mut self) {
+        self.shuffle = true;
+        self.deal_unplayed(usize::MAX);
+        if !self.unplayed.is_empty() {
+            let path = self.unplayed.swap_remove(random_below(self.unplayed.len()));
+            self.current_index = self.position_of(&path);
+        }
     }
 
     pub fn first(&self) -> Option<&AudioFile> {
@@ -51,7 +230,22 @@ impl Playlist {
         &self.files
     }
 
+    pub fn all_files_mut(&mut self) -> &mut [AudioFile] {
+        &mut self.files
+    }
+
     pub fn len(&self) -> usize {
         self.files.len()
     }
-}
\ No newline at end of file
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+}
+
+/// Picks a random index below `len`, which must be at least 1.
+fn random_below(len: usize) -> usize {
+    // Every RandomState is freshly seeded, which is plenty for picking songs
+    let random = RandomState::new().build_hasher().finish() as usize;
+    random % len
+}
@@ -1,6 +1,10 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::fs;
 use crate::audio::{AudioFile, find_audio_files};
+use crate::smart_playlist::SmartPlaylist;
+
+// Smart playlists are rule files named <name>.smart in the playlist directory
+const SMART_PLAYLIST_EXTENSION: &str = "smart";
 
 pub struct PlaylistManager {
     playlist_dir: PathBuf,
@@ -24,10 +28,10 @@ impl PlaylistManager {
         for entry in entries {
             let entry = entry?;
             let path = entry.path();
-            if path.is_dir() {
-                if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
-                    playlists.push(name.to_string());
-                }
+            if path.is_dir()
+                && let Some(name) = path.file_name().and_then(|n| n.to_str())
+            {
+                playlists.push(name.to_string());
             }
         }
         playlists.sort();
@@ -65,4 +69,114 @@ impl PlaylistManager {
         // Step 3: Use existing find_audio_files function!
         find_audio_files(&playlist_path)
     }
-}
\ No newline at end of file
+
+    /// Copies tracks into a playlist's folder. A different track with the
+    /// same file name is kept alongside under a numbered name; tracks the
+    /// playlist already has are left out and returned.
+    pub fn add_to_playlist(&self, playlist_name: &str, files: &[AudioFile]) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+        let playlist_path = self.playlist_dir.join(playlist_name);
+
+        if !playlist_path.is_dir() {
+            return Err(format!("Playlist '{}' does not exist", playlist_name).into());
+        }
+
+        let mut skipped = Vec::new();
+        for file in files {
+            let Some(file_name) = file.path.file_name() else { continue };
+            match free_target(&playlist_path, Path::new(file_name), &file.path)? {
+                Some(target) => {
+                    fs::copy(&file.path, target)?;
+                }
+                None => skipped.push(file.path.clone()),
+            }
+        }
+        Ok(skipped)
+    }
+
+    pub fn scan_smart_playlists(&self) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let mut playlists = Vec::new();
+
+        if !self.playlist_dir.exists() {
+            return Ok(playlists);
+        }
+
+        for entry in fs::read_dir(&self.playlist_dir)? {
+            let path = entry?.path();
+            if path.is_file()
+                && path.extension().is_some_and(|ext| ext == SMART_PLAYLIST_EXTENSION)
+                && let Some(name) = path.file_stem().and_then(|n| n.to_str())
+            {
+                playlists.push(name.to_string());
+            }
+        }
+        playlists.sort();
+        Ok(playlists)
+    }
+
+    pub fn load_smart_playlist(&self, name: &str) -> Result<SmartPlaylist, Box<dyn std::error::Error>> {
+        let json = fs::read_to_string(self.smart_playlist_path(name))?;
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    /// Writes the rules of a smart playlist, creating it if it's new.
+    pub fn save_smart_playlist(&self, name: &str, playlist: &SmartPlaylist) -> Result<(), Box<dyn std::error::Error>> {
+        if name.is_empty() {
+            return Err("Playlist name cannot be empty".into());
+        }
+
+        fs::create_dir_all(&self.playlist_dir)?;
+        let json = serde_json::to_string_pretty(playlist)?;
+        fs::write(self.smart_playlist_path(name), json)?;
+        Ok(())
+    }
+
+    pub fn delete_smart_playlist(&self, name: &str) -> Result<(), Box<dyn std::error::Error>> {
+        fs::remove_file(self.smart_playlist_path(name))?;
+        Ok(())
+    }
+
+    fn smart_playlist_path(&self, name: &str) -> PathBuf {
+        self.playlist_dir.join(format!("{}.{}", name, SMART_PLAYLIST_EXTENSION))
+    }
+}
+
+// Where in `folder` to copy `source` to: its own name, or "name (2).ext" and
+// so on if that's taken by another file. `None` if a copy is already there.
+fn free_target(folder: &Path, file_name: &Path, source: &Path) -> std::io::Result<Option<PathBuf>> {
+    let stem = file_name.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = file_name.extension().map(|ext| format!(".{}", ext.to_string_lossy())).unwrap_or_default();
+
+    let mut n = 1;
+    loop {
+        let target = if n == 1 { folder.join(file_name) } else { folder.join(format!("{} ({}){}", stem, n, extension)) };
+        if !target.exists() {
+            return Ok(Some(target));
+        }
+        if fs::metadata(&target)?.len() == fs::metadata(source)?.len() && fs::read(&target)? == fs::read(source)? {
+            return Ok(None);
+        }
+        n += 1;
+    }
+}
+
+/// Whether a path looks like an M3U playlist file.
+pub fn is_m3u(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("m3u") || ext.eq_ignore_ascii_case("m3u8"))
+}
+
+/// Reads the entries of an M3U playlist, in order. Relative entries are
+/// taken from the playlist's own folder.
+pub fn read_m3u(path: &Path) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    let base = path.parent().unwrap_or(Path::new(""));
+    let text = fs::read_to_string(path)?;
+    let entries = text
+        .lines()
+        .map(str::trim)
+        // Extended M3U puts its metadata on comment lines
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| base.join(line.strip_prefix("file://").unwrap_or(line)))
+        .collect();
+    Ok(entries)
+}
@@ -0,0 +1,122 @@
+use std::fs;
+use std::path::PathBuf;
+use crate::audio::AudioFile;
+use crate::playlist::Playlist;
+
+/// What plays once the current track is over.
+pub enum Advance {
+    /// The track at the front of the queue, now taken off it.
+    Queued(Box<AudioFile>),
+    /// The playlist's next track, which it has moved on to.
+    Playlist,
+    /// There's nothing left to play.
+    End,
+}
+
+/// Tracks lined up to play before the playlist carries on. They can come
+/// from any playlist, so each one is kept whole rather than as an index.
+pub struct PlayQueue {
+    queue_path: PathBuf,
+    tracks: Vec<AudioFile>,
+    // Paths saved last time, until `restore` finds their tracks
+    saved: Vec<PathBuf>,
+}
+
+impl PlayQueue {
+    pub fn new() -> Self {
+        Self::open(PathBuf::from("./library/queue.json"))
+    }
+
+    /// Opens the queue saved at `queue_path`, or starts an empty one there.
+    /// It stays empty until `restore` is given the library to find its tracks in.
+    pub fn open(queue_path: PathBuf) -> Self {
+        let saved = fs::read_to_string(&queue_path)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+
+        Self { queue_path, tracks: Vec::new(), saved }
+    }
+
+    /// Fills the queue with the tracks saved last time, taken from the
+    /// scanned library. Only the odd track from outside it is read from
+    /// disk; tracks that have gone missing are dropped.
+    pub fn restore(&mut self, library: &[AudioFile]) {
+        for path in std::mem::take(&mut self.saved) {
+            match library.iter().find(|file| file.path == path) {
+                Some(file) => self.tracks.push(file.clone()),
+                None if path.is_file() => self.tracks.push(AudioFile::new(path)),
+                None => {}
+            }
+        }
+    }
+
+    pub fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(parent) = self.queue_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let paths: Vec<&PathBuf> = self.tracks.iter().map(|track| &track.path).collect();
+        let json = serde_json::to_string_pretty(&paths)?;
+        fs::write(&self.queue_path, json)?;
+
+        Ok(())
+    }
+
+    /// Puts a track at the front, to play once the current one ends.
+    pub fn play_next(&mut self, track: AudioFile) {
+        self.tracks.insert(0, track);
+    }
+
+    pub fn add(&mut self, track: AudioFile) {
+        self.tracks.push(track);
+    }
+
+    /// Moves on from the current track: queued tracks go first, then the
+    /// playlist carries on.
+    pub fn advance(&mut self, playlist: Option<&mut Playlist>) -> Advance {
+        if let Some(track) = self.pop() {
+            return Advance::Queued(Box::new(track));
+        }
+        if let Some(playlist) = playlist
+            && playlist.next().is_some()
+        {
+            Advance::Playlist
+        } else {
+            Advance::End
+        }
+    }
+
+    /// Takes the track that should play next off the queue.
+    pub fn pop(&mut self) -> Option<AudioFile> {
+        if self.tracks.is_empty() { None } else { Some(self.tracks.remove(0)) }
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<AudioFile> {
+        if index < self.tracks.len() { Some(self.tracks.remove(index)) } else { None }
+    }
+
+    /// Moves the track at `from` so it ends up at `to`.
+    pub fn move_track(&mut self, from: usize, to: usize) {
+        if from < self.tracks.len() && to < self.tracks.len() {
+            let track = self.tracks.remove(from);
+            self.tracks.insert(to, track);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.tracks.clear();
+    }
+
+    pub fn tracks(&self) -> &[AudioFile] {
+        &self.tracks
+    }
+
+    pub fn tracks_mut(&mut self) -> &mut [AudioFile] {
+        &mut self.tracks
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tracks.is_empty()
+    }
+}
@@ -0,0 +1,165 @@
+use std::path::Path;
+use id3::TagLike;
+use id3::frame::ExtendedText;
+use serde::{Deserialize, Serialize};
+use symphonia::core::meta::{StandardTagKey, Tag};
+
+/// ReplayGain values read from a track's tags. Gains are in dB, peaks are
+/// linear sample amplitudes where 1.0 is full scale.
+#[derive(Clone, Copy, Default)]
+pub struct ReplayGain {
+    pub track_gain: Option<f32>,
+    pub track_peak: Option<f32>,
+    pub album_gain: Option<f32>,
+    pub album_peak: Option<f32>,
+}
+
+impl ReplayGain {
+    pub fn from_tags(tags: &[Tag]) -> Self {
+        let mut replay_gain = Self::default();
+
+        for tag in tags {
+            // Gains are usually written as "-6.52 dB", peaks as a bare number
+            let value = tag.value.to_string();
+            let number = value.trim().trim_end_matches("dB").trim().parse::<f32>().ok();
+
+            match tag.std_key.or_else(|| replay_gain_key(&tag.key)) {
+                Some(StandardTagKey::ReplayGainTrackGain) => replay_gain.track_gain = number,
+                Some(StandardTagKey::ReplayGainTrackPeak) => replay_gain.track_peak = number,
+                Some(StandardTagKey::ReplayGainAlbumGain) => replay_gain.album_gain = number,
+                Some(StandardTagKey::ReplayGainAlbumPeak) => replay_gain.album_peak = number,
+                _ => {}
+            }
+        }
+
+        replay_gain
+    }
+}
+
+// Symphonia only recognizes upper case TXXX descriptions, but some taggers
+// write them in lower case (e.g. "TXXX:replaygain_track_gain")
+fn replay_gain_key(key: &str) -> Option<StandardTagKey> {
+    let name = key.strip_prefix("TXXX:").unwrap_or(key).to_ascii_lowercase();
+    match name.as_str() {
+        "replaygain_track_gain" => Some(StandardTagKey::ReplayGainTrackGain),
+        "replaygain_track_peak" => Some(StandardTagKey::ReplayGainTrackPeak),
+        "replaygain_album_gain" => Some(StandardTagKey::ReplayGainAlbumGain),
+        "replaygain_album_peak" => Some(StandardTagKey::ReplayGainAlbumPeak),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ReplayGainMode {
+    Off,
+    Track,
+    Album,
+    /// Track gain while shuffling, album gain otherwise.
+    Auto,
+}
+
+impl ReplayGainMode {
+    pub const ALL: [ReplayGainMode; 4] = [
+        ReplayGainMode::Off,
+        ReplayGainMode::Track,
+        ReplayGainMode::Album,
+        ReplayGainMode::Auto,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ReplayGainMode::Off => "Off",
+            ReplayGainMode::Track => "Track",
+            ReplayGainMode::Album => "Album",
+            ReplayGainMode::Auto => "Auto (by shuffle)",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReplayGainSettings {
+    pub mode: ReplayGainMode,
+    /// Extra gain in dB applied on top of the tagged value.
+    pub preamp: f32,
+    /// Lower the gain when the tagged peak would otherwise clip.
+    pub prevent_clipping: bool,
+}
+
+impl Default for ReplayGainSettings {
+    fn default() -> Self {
+        Self {
+            mode: ReplayGainMode::Auto,
+            preamp: 0.0,
+            prevent_clipping: true,
+        }
+    }
+}
+
+impl ReplayGainSettings {
+    /// Linear amplification factor to play a track with.
+    pub fn factor(&self, replay_gain: &ReplayGain, shuffle: bool) -> f32 {
+        let use_album = match self.mode {
+            ReplayGainMode::Off => return 1.0,
+            ReplayGainMode::Track => false,
+            ReplayGainMode::Album => true,
+            ReplayGainMode::Auto => !shuffle,
+        };
+
+        // Fall back to the other kind of gain when the preferred one is missing
+        let (gain, peak) = if use_album && replay_gain.album_gain.is_some() {
+            (replay_gain.album_gain, replay_gain.album_peak)
+        } else if replay_gain.track_gain.is_some() {
+            (replay_gain.track_gain, replay_gain.track_peak)
+        } else {
+            (replay_gain.album_gain, replay_gain.album_peak)
+        };
+
+        let Some(gain) = gain else {
+            return 1.0;
+        };
+
+        let mut factor = db_to_factor(gain + self.preamp);
+        if self.prevent_clipping
+            && let Some(peak) = peak
+            && peak > 0.0
+        {
+            factor = factor.min(1.0 / peak);
+        }
+        factor
+    }
+}
+
+/// Stores track gain and peak in the file's ID3v2 tag, keeping its other tags.
+pub fn write_track_tags(path: &Path, replay_gain: &ReplayGain) -> Result<(), Box<dyn std::error::Error>> {
+    // id3 picks the right place for the tag (WAV chunk or MP3 header) by itself
+    let mut tag = id3::partial_tag_ok(id3::Tag::read_from_path(path)).or_else(|e| match e.kind {
+        id3::ErrorKind::NoTag => Ok(id3::Tag::new()),
+        _ => Err(e),
+    })?;
+
+    let mut set = |description: &str, value: String| {
+        // Drop any old value, whatever case its description was written in
+        let stale: Vec<String> = tag.extended_texts()
+            .filter(|text| text.description.eq_ignore_ascii_case(description))
+            .map(|text| text.description.clone())
+            .collect();
+        for old in &stale {
+            tag.remove_extended_text(Some(old), None);
+        }
+        tag.add_frame(ExtendedText { description: description.to_string(), value });
+    };
+    if let Some(gain) = replay_gain.track_gain {
+        set("REPLAYGAIN_TRACK_GAIN", format!("{:.2} dB", gain));
+    }
+    if let Some(peak) = replay_gain.track_peak {
+        set("REPLAYGAIN_TRACK_PEAK", format!("{:.6}", peak));
+    }
+
+    tag.write_to_path(path, id3::Version::Id3v24)?;
+    Ok(())
+}
+
+pub fn db_to_factor(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
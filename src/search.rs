@@ -0,0 +1,80 @@
+use crate::audio::AudioFile;
+
+// What lands a match higher up: letters in a row, letters at the start of a
+// word, and the whole term appearing as is
+const CONSECUTIVE_BONUS: i32 = 5;
+const WORD_START_BONUS: i32 = 8;
+const SUBSTRING_BONUS: i32 = 20;
+
+/// Scores how well `term` (lowercase) matches `text` when its characters
+/// are picked out of `text` in order, or `None` if they can't all be found.
+fn fuzzy_score(term: &str, text: &str) -> Option<i32> {
+    let text = text.to_lowercase();
+    let mut wanted = term.chars().peekable();
+    let mut score = 0;
+    let mut first_match = None;
+    let mut previous: Option<char> = None;
+    let mut previous_matched = false;
+
+    for (i, c) in text.chars().enumerate() {
+        let Some(&next) = wanted.peek() else { break };
+        if c == next {
+            wanted.next();
+            score += 1;
+            if previous_matched {
+                score += CONSECUTIVE_BONUS;
+            }
+            if previous.is_none_or(|p| !p.is_alphanumeric()) {
+                score += WORD_START_BONUS;
+            }
+            first_match.get_or_insert(i as i32);
+            previous_matched = true;
+        } else {
+            previous_matched = false;
+        }
+        previous = Some(c);
+    }
+
+    if wanted.peek().is_some() {
+        return None;
+    }
+    if text.contains(term) {
+        score += SUBSTRING_BONUS;
+    }
+    // A match further into the text counts for a little less
+    Some(score - first_match.unwrap_or(0).min(10))
+}
+
+/// Indices of the files matching every word of `query`, best match first.
+/// Each word may match the title, artist, album or path, with a match in
+/// the title counting the most.
+pub fn search(files: &[AudioFile], query: &str) -> Vec<usize> {
+    let query = query.to_lowercase();
+    let terms: Vec<&str> = query.split_whitespace().collect();
+
+    let mut hits: Vec<(usize, i32)> = files
+        .iter()
+        .enumerate()
+        .filter_map(|(index, file)| {
+            let path = file.path.to_string_lossy();
+            let fields = [
+                (Some(file.title.as_str()), 3),
+                (file.tags.artist.as_deref(), 2),
+                (file.tags.album.as_deref(), 2),
+                (Some(path.as_ref()), 1),
+            ];
+            let mut total = 0;
+            for term in &terms {
+                total += fields
+                    .iter()
+                    .filter_map(|(text, weight)| Some(fuzzy_score(term, (*text)?)? * weight))
+                    .max()?;
+            }
+            Some((index, total))
+        })
+        .collect();
+
+    // Stable, so equally good matches keep their playlist order
+    hits.sort_by_key(|(_, score)| std::cmp::Reverse(*score));
+    hits.into_iter().map(|(index, _)| index).collect()
+}
@@ -0,0 +1,67 @@
+use std::fs;
+use std::path::PathBuf;
+use serde::{Deserialize, Deserializer, Serialize};
+use crate::equalizer::EqSettings;
+use crate::fade::FadeSettings;
+use crate::keybindings::KeyBindings;
+use crate::loudness::LoudnessSettings;
+use crate::output::OutputTarget;
+use crate::replaygain::ReplayGainSettings;
+use crate::sort::SortKey;
+use crate::stereo::StereoSettings;
+use crate::timestretch::SpeedSettings;
+use crate::visualizer::VisualizerSettings;
+
+/// User preferences that survive a restart.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub replay_gain: ReplayGainSettings,
+    pub loudness: LoudnessSettings,
+    #[serde(alias = "output_device", deserialize_with = "deserialize_output")]
+    pub output: OutputTarget,
+    pub equalizer: EqSettings,
+    pub stereo: StereoSettings,
+    pub speed: SpeedSettings,
+    pub fade: FadeSettings,
+    pub visualizer: VisualizerSettings,
+    pub keybindings: KeyBindings,
+    /// Track list order, most significant key first.
+    pub track_sort: Vec<SortKey>,
+}
+
+// Settings saved before there were outputs other than sound cards only
+// have the device's name, `null` being the system default
+fn deserialize_output<'de, D: Deserializer<'de>>(deserializer: D) -> Result<OutputTarget, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StoredOutput {
+        Target(OutputTarget),
+        DeviceName(Option<String>),
+    }
+
+    Ok(match StoredOutput::deserialize(deserializer)? {
+        StoredOutput::Target(target) => target,
+        StoredOutput::DeviceName(name) => OutputTarget::Device(name),
+    })
+}
+
+impl Settings {
+    fn path() -> PathBuf {
+        PathBuf::from("./settings.json")
+    }
+
+    pub fn load() -> Self {
+        // Fall back to defaults rather than refusing to start over a bad file
+        fs::read_to_string(Self::path())
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(Self::path(), json)?;
+        Ok(())
+    }
+}
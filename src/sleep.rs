@@ -0,0 +1,70 @@
+use std::time::{Duration, Instant};
+
+/// How long the volume takes to fade out before the sleep timer stops playback.
+pub const SLEEP_FADE: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum SleepMode {
+    After(Duration),
+    EndOfTrack,
+    EndOfPlaylist,
+}
+
+impl SleepMode {
+    pub fn label(&self) -> String {
+        match self {
+            SleepMode::After(duration) => format!("After {} min", duration.as_secs() / 60),
+            SleepMode::EndOfTrack => "End of track".to_string(),
+            SleepMode::EndOfPlaylist => "End of playlist".to_string(),
+        }
+    }
+}
+
+/// Stops playback at a set time. Timers that follow the music get their
+/// deadline moved as playback goes on, and have none while how much is left
+/// to play isn't known; those still stop when the music gets there.
+pub struct SleepTimer {
+    pub mode: SleepMode,
+    deadline: Option<Instant>,
+}
+
+impl SleepTimer {
+    /// `remaining` is how much is left to play, used by the modes that
+    /// follow the music.
+    pub fn start(mode: SleepMode, remaining: Option<Duration>) -> Self {
+        let deadline = match mode {
+            SleepMode::After(duration) => Some(duration),
+            SleepMode::EndOfTrack | SleepMode::EndOfPlaylist => remaining,
+        };
+        Self { mode, deadline: deadline.map(|duration| Instant::now() + duration) }
+    }
+
+    /// Moves the deadline of a timer that follows the music.
+    pub fn update_remaining(&mut self, remaining: Option<Duration>) {
+        if !matches!(self.mode, SleepMode::After(_)) {
+            self.deadline = remaining.map(|remaining| Instant::now() + remaining);
+        }
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    pub fn remaining(&self) -> Option<Duration> {
+        self.deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()))
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.deadline.is_some_and(|deadline| Instant::now() >= deadline)
+    }
+}
+
+/// Volume factor on the way to `deadline`, falling to silence over the last
+/// `SLEEP_FADE`.
+pub fn fade_factor(deadline: Option<Instant>) -> f32 {
+    let Some(deadline) = deadline else { return 1.0 };
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    let linear = (remaining.as_secs_f32() / SLEEP_FADE.as_secs_f32()).min(1.0);
+    // Squared so the fade sounds even rather than dropping away at the end
+    linear * linear
+}
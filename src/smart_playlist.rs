@@ -0,0 +1,283 @@
+use std::borrow::Cow;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::time::{Duration, SystemTime};
+use serde::{Deserialize, Serialize};
+use crate::audio::AudioFile;
+
+/// What a rule looks at in a track.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum RuleField {
+    Title,
+    Artist,
+    Album,
+    Genre,
+    Path,
+    Year,
+    Rating,
+    PlayCount,
+    SkipCount,
+    LastPlayed,
+    DateAdded,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum FieldKind {
+    Text,
+    Number,
+    Date,
+}
+
+impl RuleField {
+    pub const ALL: [RuleField; 11] = [
+        RuleField::Title,
+        RuleField::Artist,
+        RuleField::Album,
+        RuleField::Genre,
+        RuleField::Path,
+        RuleField::Year,
+        RuleField::Rating,
+        RuleField::PlayCount,
+        RuleField::SkipCount,
+        RuleField::LastPlayed,
+        RuleField::DateAdded,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            RuleField::Title => "Title",
+            RuleField::Artist => "Artist",
+            RuleField::Album => "Album",
+            RuleField::Genre => "Genre",
+            RuleField::Path => "Path",
+            RuleField::Year => "Year",
+            RuleField::Rating => "Rating",
+            RuleField::PlayCount => "Play count",
+            RuleField::SkipCount => "Skip count",
+            RuleField::LastPlayed => "Last played",
+            RuleField::DateAdded => "Date added",
+        }
+    }
+
+    pub fn kind(self) -> FieldKind {
+        match self {
+            RuleField::Title | RuleField::Artist | RuleField::Album | RuleField::Genre | RuleField::Path => FieldKind::Text,
+            RuleField::Year | RuleField::Rating | RuleField::PlayCount | RuleField::SkipCount => FieldKind::Number,
+            RuleField::LastPlayed | RuleField::DateAdded => FieldKind::Date,
+        }
+    }
+}
+
+/// How a rule compares the field with its value.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum RuleOp {
+    Is,
+    IsNot,
+    Contains,
+    AtLeast,
+    AtMost,
+    /// Within the last so many days.
+    Within,
+    /// Not within the last so many days, or never.
+    NotWithin,
+}
+
+impl RuleOp {
+    /// The comparisons that make sense for a kind of field.
+    pub fn for_kind(kind: FieldKind) -> &'static [RuleOp] {
+        match kind {
+            FieldKind::Text => &[RuleOp::Is, RuleOp::IsNot, RuleOp::Contains],
+            FieldKind::Number => &[RuleOp::Is, RuleOp::IsNot, RuleOp::AtLeast, RuleOp::AtMost],
+            FieldKind::Date => &[RuleOp::Within, RuleOp::NotWithin],
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            RuleOp::Is => "is",
+            RuleOp::IsNot => "is not",
+            RuleOp::Contains => "contains",
+            RuleOp::AtLeast => "≥",
+            RuleOp::AtMost => "≤",
+            RuleOp::Within => "in the last",
+            RuleOp::NotWithin => "not in the last",
+        }
+    }
+}
+
+/// One condition on a track. Text fields compare against `text`; numbers
+/// and dates, as days, against `number`.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Rule {
+    pub field: RuleField,
+    pub op: RuleOp,
+    pub text: String,
+    pub number: u32,
+}
+
+impl Rule {
+    pub fn new(field: RuleField) -> Self {
+        Self { field, op: RuleOp::for_kind(field.kind())[0], text: String::new(), number: 0 }
+    }
+
+    /// Switches the rule to another field, keeping the comparison if it
+    /// still applies.
+    pub fn set_field(&mut self, field: RuleField) {
+        self.field = field;
+        if !RuleOp::for_kind(field.kind()).contains(&self.op) {
+            self.op = RuleOp::for_kind(field.kind())[0];
+        }
+    }
+
+    fn matches(&self, file: &AudioFile, now: SystemTime) -> bool {
+        match self.field.value(file) {
+            FieldValue::Text(value) => {
+                let value = value.map(|value| value.to_lowercase());
+                let wanted = self.text.trim().to_lowercase();
+                match self.op {
+                    RuleOp::Is => value.is_some_and(|value| value == wanted),
+                    RuleOp::IsNot => value.is_none_or(|value| value != wanted),
+                    RuleOp::Contains => value.is_some_and(|value| value.contains(&wanted)),
+                    RuleOp::AtLeast | RuleOp::AtMost | RuleOp::Within | RuleOp::NotWithin => false,
+                }
+            }
+            FieldValue::Number(value) => match self.op {
+                RuleOp::Is => value == Some(self.number),
+                RuleOp::IsNot => value != Some(self.number),
+                RuleOp::AtLeast => value.is_some_and(|value| value >= self.number),
+                RuleOp::AtMost => value.is_some_and(|value| value <= self.number),
+                RuleOp::Contains | RuleOp::Within | RuleOp::NotWithin => false,
+            },
+            FieldValue::Date(time) => {
+                let since = now.checked_sub(Duration::from_secs(self.number as u64 * 86_400)).unwrap_or(SystemTime::UNIX_EPOCH);
+                let within = time.is_some_and(|time| time >= since);
+                match self.op {
+                    RuleOp::Within => within,
+                    RuleOp::NotWithin => !within,
+                    RuleOp::Is | RuleOp::IsNot | RuleOp::Contains | RuleOp::AtLeast | RuleOp::AtMost => false,
+                }
+            }
+        }
+    }
+}
+
+// What a field holds for one track, if anything
+enum FieldValue<'a> {
+    Text(Option<Cow<'a, str>>),
+    Number(Option<u32>),
+    Date(Option<SystemTime>),
+}
+
+impl RuleField {
+    fn value(self, file: &AudioFile) -> FieldValue<'_> {
+        match self {
+            RuleField::Title => FieldValue::Text(Some(Cow::from(file.title.as_str()))),
+            RuleField::Artist => FieldValue::Text(file.tags.artist.as_deref().map(Cow::from)),
+            RuleField::Album => FieldValue::Text(file.tags.album.as_deref().map(Cow::from)),
+            RuleField::Genre => FieldValue::Text(file.tags.genre.as_deref().map(Cow::from)),
+            RuleField::Path => FieldValue::Text(Some(file.path.to_string_lossy())),
+            RuleField::Year => FieldValue::Number(file.tags.year),
+            RuleField::Rating => FieldValue::Number(Some(file.stats.rating as u32)),
+            RuleField::PlayCount => FieldValue::Number(Some(file.stats.play_count)),
+            RuleField::SkipCount => FieldValue::Number(Some(file.stats.skip_count)),
+            RuleField::LastPlayed => FieldValue::Date(file.stats.last_played),
+            RuleField::DateAdded => FieldValue::Date(file.added),
+        }
+    }
+}
+
+/// The order a smart playlist's tracks come out in, before the limit.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum SmartOrder {
+    /// The track list's own sort.
+    TrackList,
+    Random,
+    HighestRated,
+    MostPlayed,
+    LeastPlayed,
+    RecentlyPlayed,
+    RecentlyAdded,
+}
+
+impl SmartOrder {
+    pub const ALL: [SmartOrder; 7] = [
+        SmartOrder::TrackList,
+        SmartOrder::Random,
+        SmartOrder::HighestRated,
+        SmartOrder::MostPlayed,
+        SmartOrder::LeastPlayed,
+        SmartOrder::RecentlyPlayed,
+        SmartOrder::RecentlyAdded,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SmartOrder::TrackList => "Track list order",
+            SmartOrder::Random => "Random",
+            SmartOrder::HighestRated => "Highest rated",
+            SmartOrder::MostPlayed => "Most played",
+            SmartOrder::LeastPlayed => "Least played",
+            SmartOrder::RecentlyPlayed => "Recently played",
+            SmartOrder::RecentlyAdded => "Recently added",
+        }
+    }
+}
+
+/// A playlist made of whichever library tracks match its rules.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SmartPlaylist {
+    pub rules: Vec<Rule>,
+    /// Whether a track has to match every rule, or just one.
+    pub match_all: bool,
+    pub limit: Option<usize>,
+    pub order: SmartOrder,
+}
+
+impl Default for SmartPlaylist {
+    fn default() -> Self {
+        Self { rules: vec![Rule::new(RuleField::Genre)], match_all: true, limit: None, order: SmartOrder::TrackList }
+    }
+}
+
+impl SmartPlaylist {
+    /// The tracks from `files` that belong in the playlist, in its order.
+    pub fn evaluate(&self, files: &[AudioFile]) -> Vec<AudioFile> {
+        let now = SystemTime::now();
+        let mut matches: Vec<AudioFile> = files.iter().filter(|file| self.matches(file, now)).cloned().collect();
+
+        match self.order {
+            SmartOrder::TrackList => {}
+            SmartOrder::Random => {
+                // A freshly seeded hash of each path makes a new shuffle every time
+                let random = RandomState::new();
+                matches.sort_by_cached_key(|file| random.hash_one(&file.path));
+            }
+            SmartOrder::HighestRated => matches.sort_by_key(|file| std::cmp::Reverse(file.stats.rating)),
+            SmartOrder::MostPlayed => matches.sort_by_key(|file| std::cmp::Reverse(file.stats.play_count)),
+            SmartOrder::LeastPlayed => matches.sort_by_key(|file| file.stats.play_count),
+            SmartOrder::RecentlyPlayed => matches.sort_by_key(|file| std::cmp::Reverse(file.stats.last_played)),
+            SmartOrder::RecentlyAdded => matches.sort_by_key(|file| std::cmp::Reverse(file.added)),
+        }
+
+        if let Some(limit) = self.limit {
+            matches.truncate(limit);
+        }
+        matches
+    }
+
+    /// How many tracks `evaluate` would give, without collecting or ordering them.
+    pub fn count(&self, files: &[AudioFile]) -> usize {
+        let now = SystemTime::now();
+        let count = files.iter().filter(|file| self.matches(file, now)).count();
+        self.limit.map_or(count, |limit| count.min(limit))
+    }
+
+    fn matches(&self, file: &AudioFile, now: SystemTime) -> bool {
+        if self.match_all {
+            self.rules.iter().all(|rule| rule.matches(file, now))
+        } else {
+            self.rules.iter().any(|rule| rule.matches(file, now))
+        }
+    }
+}
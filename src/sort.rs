@@ -0,0 +1,134 @@
+use std::cmp::Ordering;
+use serde::{Deserialize, Serialize};
+use crate::audio::AudioFile;
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum SortColumn {
+    Title,
+    Artist,
+    /// Album, then disc and track number within it.
+    Album,
+    TrackNumber,
+    Duration,
+    DateAdded,
+    Path,
+    PlayCount,
+    SkipCount,
+    LastPlayed,
+    Rating,
+}
+
+/// One level of a track list sort.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SortKey {
+    pub column: SortColumn,
+    pub descending: bool,
+}
+
+/// Puts `files` in order by `keys`. Without keys a single album goes in
+/// disc and track order, and anything else by path.
+pub fn sort_files(files: &mut [AudioFile], keys: &[SortKey]) {
+    let default_column = if is_one_album(files) { SortColumn::Album } else { SortColumn::Path };
+    let default_keys = [SortKey { column: default_column, descending: false }];
+    let keys = if keys.is_empty() { &default_keys[..] } else { keys };
+    files.sort_by(|a, b| compare_files(a, b, keys));
+}
+
+/// Orders tracks about to be shown: a single album always opens in disc and
+/// track order, whatever the list was last sorted by, and anything else goes
+/// by `keys`.
+pub fn sort_for_showing(files: &mut [AudioFile], keys: &[SortKey]) {
+    let keys = if is_one_album(files) { &[] } else { keys };
+    sort_files(files, keys);
+}
+
+fn is_one_album(files: &[AudioFile]) -> bool {
+    match files.first().and_then(|file| file.tags.album.as_ref()) {
+        Some(album) => files.iter().all(|file| file.tags.album.as_ref() == Some(album)),
+        None => false,
+    }
+}
+
+/// Orders `a` and `b` by each key in turn, falling back to the path so the
+/// order never depends on how the files were found.
+fn compare_files(a: &AudioFile, b: &AudioFile, keys: &[SortKey]) -> Ordering {
+    keys.iter()
+        .map(|key| {
+            let ordering = compare_by(a, b, key.column);
+            if key.descending { ordering.reverse() } else { ordering }
+        })
+        .find(|ordering| ordering.is_ne())
+        .unwrap_or_else(|| compare_by(a, b, SortColumn::Path))
+}
+
+fn compare_by(a: &AudioFile, b: &AudioFile, column: SortColumn) -> Ordering {
+    match column {
+        SortColumn::Title => natural_cmp(&a.title, &b.title),
+        SortColumn::Artist => missing_last(a.tags.artist.as_ref(), b.tags.artist.as_ref(), |a, b| natural_cmp(a, b)),
+        SortColumn::Album => missing_last(a.tags.album.as_ref(), b.tags.album.as_ref(), |a, b| natural_cmp(a, b))
+            .then_with(|| missing_last(a.tags.disc_number, b.tags.disc_number, Ord::cmp))
+            .then_with(|| compare_by(a, b, SortColumn::TrackNumber)),
+        SortColumn::TrackNumber => missing_last(a.tags.track_number, b.tags.track_number, Ord::cmp),
+        SortColumn::Duration => missing_last(a.duration, b.duration, Ord::cmp),
+        SortColumn::DateAdded => missing_last(a.added, b.added, Ord::cmp),
+        SortColumn::Path => natural_cmp(&a.path.to_string_lossy(), &b.path.to_string_lossy()),
+        SortColumn::PlayCount => a.stats.play_count.cmp(&b.stats.play_count),
+        SortColumn::SkipCount => a.stats.skip_count.cmp(&b.stats.skip_count),
+        SortColumn::LastPlayed => missing_last(a.stats.last_played, b.stats.last_played, Ord::cmp),
+        SortColumn::Rating => a.stats.rating.cmp(&b.stats.rating),
+    }
+}
+
+// Tracks without a value go after the ones that have it
+fn missing_last<T>(a: Option<T>, b: Option<T>, cmp: impl Fn(&T, &T) -> Ordering) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => cmp(&a, &b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+/// Compares strings the way people read them: case doesn't matter and runs
+/// of digits compare as numbers, so "2 - x" comes before "10 - y".
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a = a.chars().peekable();
+    let mut b = b.chars().peekable();
+
+    loop {
+        let (Some(&x), Some(&y)) = (a.peek(), b.peek()) else {
+            return a.peek().is_some().cmp(&b.peek().is_some());
+        };
+
+        if x.is_ascii_digit() && y.is_ascii_digit() {
+            let x_digits = take_digits(&mut a);
+            let y_digits = take_digits(&mut b);
+            // Without leading zeros, the longer run is the bigger number
+            let x_number = x_digits.trim_start_matches('0');
+            let y_number = y_digits.trim_start_matches('0');
+            let ordering = x_number
+                .len()
+                .cmp(&y_number.len())
+                .then_with(|| x_number.cmp(y_number))
+                .then_with(|| x_digits.len().cmp(&y_digits.len()));
+            if ordering.is_ne() {
+                return ordering;
+            }
+        } else {
+            let ordering = x.to_lowercase().cmp(y.to_lowercase());
+            if ordering.is_ne() {
+                return ordering;
+            }
+            a.next();
+            b.next();
+        }
+    }
+}
+
+fn take_digits(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+    let mut digits = String::new();
+    while let Some(c) = chars.next_if(char::is_ascii_digit) {
+        digits.push(c);
+    }
+    digits
+}
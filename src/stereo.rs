@@ -0,0 +1,105 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use rodio::source::SeekError;
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StereoSettings {
+    /// -1.0 is fully left, 1.0 fully right.
+    pub balance: f32,
+    /// Play the same mix of both channels on each side.
+    pub mono: bool,
+    /// Play the left channel on the right and vice versa.
+    pub swap: bool,
+}
+
+impl StereoSettings {
+    fn gains(&self) -> (f32, f32) {
+        // Turning towards one side only ever turns the other one down
+        ((1.0 - self.balance).min(1.0), (1.0 + self.balance).min(1.0))
+    }
+}
+
+/// Applies `StereoSettings` to two-channel sources; anything else passes
+/// through untouched.
+pub struct StereoMix<S> {
+    input: S,
+    shared: Arc<Mutex<StereoSettings>>,
+    settings: StereoSettings,
+    // Right channel of the frame whose left channel was just handed out
+    pending_right: Option<f32>,
+}
+
+impl<S: Source> StereoMix<S> {
+    pub fn new(input: S, shared: Arc<Mutex<StereoSettings>>) -> Self {
+        let settings = *shared.lock().unwrap();
+        Self { input, shared, settings, pending_right: None }
+    }
+
+    /// Picks up settings changed from the UI.
+    pub fn refresh(&mut self) {
+        self.settings = *self.shared.lock().unwrap();
+    }
+
+    pub fn inner_mut(&mut self) -> &mut S {
+        &mut self.input
+    }
+}
+
+impl<S: Source> Iterator for StereoMix<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if let Some(right) = self.pending_right.take() {
+            return Some(right);
+        }
+        if self.input.channels() != 2 {
+            return self.input.next();
+        }
+
+        let mut left = self.input.next()?;
+        let Some(mut right) = self.input.next() else { return Some(left) };
+
+        if self.settings.swap {
+            (left, right) = (right, left);
+        }
+        if self.settings.mono {
+            let mid = (left + right) / 2.0;
+            (left, right) = (mid, mid);
+        }
+        let (left_gain, right_gain) = self.settings.gains();
+
+        self.pending_right = Some(right * right_gain);
+        Some(left * left_gain)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<S: Source> Source for StereoMix<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        self.input.current_span_len()
+    }
+
+    fn channels(&self) -> rodio::ChannelCount {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> rodio::SampleRate {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)?;
+        self.pending_right = None;
+        Ok(())
+    }
+}
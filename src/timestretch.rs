@@ -0,0 +1,397 @@
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use rodio::source::SeekError;
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+
+pub const MIN_SPEED: f32 = 0.5;
+pub const MAX_SPEED: f32 = 3.0;
+pub const MAX_PITCH_SHIFT: f32 = 12.0;
+
+// WSOLA: segments of WINDOW overlap by half, and each may slide by up to
+// SEARCH_RANGE to line up with the one before it
+const WINDOW: Duration = Duration::from_millis(40);
+const SEARCH_RANGE: Duration = Duration::from_millis(10);
+// Only every few candidates and frames are compared when lining segments up
+const CANDIDATE_STRIDE: usize = 2;
+const CORRELATION_STRIDE: usize = 4;
+// Each span costs the mixer's resampler a little at its edges, so they
+// shouldn't be too short; settings changes are picked up between spans
+const MIN_SPAN_FRAMES: usize = 2048;
+
+/// How fast and at what pitch tracks play.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SpeedSettings {
+    /// Playback rate, 1.0 being normal speed.
+    pub speed: f32,
+    /// Stretch time rather than play faster like a tape would.
+    pub keep_pitch: bool,
+    /// Pitch shift on top of whatever the speed does.
+    pub semitones: f32,
+}
+
+impl Default for SpeedSettings {
+    fn default() -> Self {
+        Self { speed: 1.0, keep_pitch: true, semitones: 0.0 }
+    }
+}
+
+impl SpeedSettings {
+    /// Factor the audio is resampled by, which moves pitch and speed alike.
+    fn resample_factor(&self) -> f64 {
+        let pitch = 2f64.powf(self.semitones as f64 / 12.0);
+        if self.keep_pitch { pitch } else { pitch * self.speed as f64 }
+    }
+
+    /// Speed change that's left for the time stretcher after resampling.
+    fn stretch_factor(&self) -> f64 {
+        self.speed as f64 / self.resample_factor()
+    }
+}
+
+/// Changes the speed and pitch of a source. Tempo is changed by WSOLA time
+/// stretching, pitch by reporting a different sample rate so the mixer
+/// resamples it. Keeps track of the position in the source's own time.
+pub struct TimeStretch<S> {
+    input: S,
+    shared: Arc<Mutex<SpeedSettings>>,
+    settings: SpeedSettings,
+    // Seconds into the source, stored as f64 bits
+    position: Arc<AtomicU64>,
+    seconds: f64,
+    // Source time at frame 0 of the buffer's numbering
+    start_seconds: f64,
+
+    // Format of the input the state below was set up for
+    input_rate: u32,
+    channels: usize,
+    window: Vec<f32>,
+    search: i64,
+    input_done: bool,
+    format_changed: bool,
+
+    // Input that hasn't been passed on yet or that the stretcher may still
+    // need, starting at absolute frame `buffer_start`
+    buffer: VecDeque<f32>,
+    buffer_start: i64,
+    // Start of the last segment added to the output, `None` while the
+    // input is passed through untouched
+    segment_start: Option<i64>,
+    analysis_pos: f64,
+    // Second half of the last segment, still to be overlapped with the next
+    tail: Vec<f32>,
+
+    // Output handed out as one span, with its format and the source time
+    // each of its frames stands for
+    span: VecDeque<f32>,
+    span_rate: u32,
+    span_channels: u16,
+    span_step: f64,
+    span_channel: usize,
+}
+
+impl<S: Source> TimeStretch<S> {
+    pub fn new(input: S, shared: Arc<Mutex<SpeedSettings>>, position: Arc<AtomicU64>) -> Self {
+        let settings = *shared.lock().unwrap();
+        let mut stretch = Self {
+            input,
+            shared,
+            settings,
+            position,
+            seconds: 0.0,
+            start_seconds: 0.0,
+            input_rate: 0,
+            channels: 0,
+            window: Vec::new(),
+            search: 0,
+            input_done: false,
+            format_changed: false,
+            buffer: VecDeque::new(),
+            buffer_start: 0,
+            segment_start: None,
+            analysis_pos: 0.0,
+            tail: Vec::new(),
+            span: VecDeque::new(),
+            span_rate: 1,
+            span_channels: 1,
+            span_step: 0.0,
+            span_channel: 0,
+        };
+        stretch.reset();
+        stretch.position.store(0f64.to_bits(), Ordering::Relaxed);
+        stretch.fill_span();
+        stretch
+    }
+
+    /// Picks up new settings; they apply from the next span on.
+    pub fn refresh(&mut self) {
+        self.settings = *self.shared.lock().unwrap();
+    }
+
+    pub fn inner_mut(&mut self) -> &mut S {
+        &mut self.input
+    }
+
+    // Drops all state and sets up for the input's current format
+    fn reset(&mut self) {
+        self.input_rate = self.input.sample_rate();
+        self.channels = (self.input.channels() as usize).max(1);
+        let window_len = (self.input_rate as f64 * WINDOW.as_secs_f64()) as usize & !1;
+        self.window = (0..window_len)
+            .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / window_len as f32).cos())
+            .collect();
+        self.search = (self.input_rate as f64 * SEARCH_RANGE.as_secs_f64()) as i64;
+        self.start_seconds = self.seconds;
+        self.format_changed = false;
+        self.buffer.clear();
+        self.buffer_start = 0;
+        self.segment_start = None;
+        self.tail.clear();
+    }
+
+    fn hop(&self) -> usize {
+        self.window.len() / 2
+    }
+
+    fn buffer_end(&self) -> i64 {
+        self.buffer_start + (self.buffer.len() / self.channels) as i64
+    }
+
+    fn sample(&self, frame: i64, channel: usize) -> f32 {
+        if frame < self.buffer_start {
+            return 0.0;
+        }
+        let index = (frame - self.buffer_start) as usize * self.channels + channel;
+        self.buffer.get(index).copied().unwrap_or(0.0)
+    }
+
+    fn frame_sum(&self, frame: i64) -> f32 {
+        (0..self.channels).map(|channel| self.sample(frame, channel)).sum()
+    }
+
+    // Moves one frame from the input into the buffer
+    fn read_frame(&mut self) -> bool {
+        if self.input_done || self.format_changed {
+            return false;
+        }
+        if self.input.sample_rate() != self.input_rate || self.input.channels() as usize != self.channels {
+            self.format_changed = true;
+            return false;
+        }
+        for read in 0..self.channels {
+            match self.input.next() {
+                Some(sample) => self.buffer.push_back(sample),
+                None => {
+                    self.input_done = true;
+                    self.buffer.truncate(self.buffer.len() - read);
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    // Reads until the buffer reaches `end`, or the input runs out
+    fn fill_buffer(&mut self, end: i64) -> bool {
+        while self.buffer_end() < end {
+            if !self.read_frame() {
+                return false;
+            }
+        }
+        true
+    }
+
+    fn discard_before(&mut self, frame: i64) {
+        let frames = (frame - self.buffer_start).clamp(0, self.buffer_end() - self.buffer_start);
+        self.buffer.drain(..frames as usize * self.channels);
+        self.buffer_start += frames;
+    }
+
+    fn fill_span(&mut self) {
+        loop {
+            if self.format_changed {
+                self.reset();
+            }
+
+            let stretch = self.settings.stretch_factor();
+            let stretching = (stretch - 1.0).abs() > 1e-3;
+            if !stretching {
+                self.stop_stretching();
+            }
+            self.span_rate = ((self.input_rate as f64 * self.settings.resample_factor()).round() as u32).max(1);
+            self.span_channels = self.channels as u16;
+            self.span_step = (if stretching { stretch } else { 1.0 }) / self.input_rate.max(1) as f64;
+            self.span_channel = 0;
+
+            while self.span.len() < MIN_SPAN_FRAMES * self.channels {
+                let produced = if stretching { self.stretch_segment(stretch) } else { self.pass_frame() };
+                if !produced {
+                    break;
+                }
+            }
+
+            if !self.span.is_empty() || !self.format_changed {
+                return;
+            }
+        }
+    }
+
+    fn pass_frame(&mut self) -> bool {
+        if self.buffer.is_empty() && !self.read_frame() {
+            return false;
+        }
+        self.span.extend(self.buffer.drain(..self.channels));
+        self.buffer_start += 1;
+        true
+    }
+
+    fn stop_stretching(&mut self) {
+        // The tail and the rising half of the segment that naturally follows
+        // it add back up to the untouched input, so carry on from there
+        if let Some(start) = self.segment_start.take() {
+            self.discard_before(start + self.hop() as i64);
+            self.tail.clear();
+        }
+    }
+
+    // Overlap-adds the next segment, adding one hop of output to the span
+    fn stretch_segment(&mut self, stretch: f64) -> bool {
+        let hop = self.hop();
+        let previous = match self.segment_start {
+            Some(start) => start,
+            None => {
+                // Coming from untouched playback: act as if the last segment
+                // had been the input itself
+                let start = self.buffer_start;
+                if !self.fill_buffer(start + 1) {
+                    return false;
+                }
+                self.fill_buffer(start + hop as i64);
+                self.tail = (0..hop * self.channels)
+                    .map(|i| self.window[hop + i / self.channels] * self.sample(start + (i / self.channels) as i64, i % self.channels))
+                    .collect();
+                self.analysis_pos = (start - hop as i64) as f64;
+                start - hop as i64
+            }
+        };
+
+        self.analysis_pos += hop as f64 * stretch;
+        let natural = previous + hop as i64;
+        let nominal = self.analysis_pos.round() as i64;
+        self.fill_buffer(natural + hop as i64);
+        self.fill_buffer(nominal + self.search + self.window.len() as i64);
+
+        // Out of input: all that's left is the tail of the last segment
+        if natural >= self.buffer_end() {
+            self.span.extend(self.tail.drain(..));
+            self.segment_start = None;
+            self.discard_before(natural);
+            return !self.span.is_empty();
+        }
+
+        let start = self.best_segment_start(natural, nominal);
+        for i in 0..hop {
+            for channel in 0..self.channels {
+                let index = i * self.channels + channel;
+                let rising = self.window[i] * self.sample(start + i as i64, channel);
+                self.span.push_back(self.tail[index] + rising);
+                self.tail[index] = self.window[hop + i] * self.sample(start + (hop + i) as i64, channel);
+            }
+        }
+        self.segment_start = Some(start);
+
+        let next_nominal = (self.analysis_pos + hop as f64 * stretch).round() as i64;
+        self.discard_before((next_nominal - self.search).min(start + hop as i64));
+        true
+    }
+
+    // Finds the segment start near `nominal` whose beginning best matches how
+    // the previous segment would have continued at `natural`
+    fn best_segment_start(&self, natural: i64, nominal: i64) -> i64 {
+        let hop = self.hop() as i64;
+        let low = (nominal - self.search).max(self.buffer_start);
+        let high = (nominal + self.search).min(self.buffer_end() - 1);
+        if low > high {
+            return nominal.clamp(self.buffer_start, self.buffer_end() - 1);
+        }
+
+        let reference: Vec<f32> = (0..hop)
+            .step_by(CORRELATION_STRIDE)
+            .map(|i| self.frame_sum(natural + i))
+            .collect();
+
+        let mut best = (f32::MIN, nominal.clamp(low, high));
+        for candidate in (low..=high).step_by(CANDIDATE_STRIDE) {
+            let (mut correlation, mut energy) = (0.0, 0.0);
+            for (i, reference) in (0..hop).step_by(CORRELATION_STRIDE).zip(&reference) {
+                let value = self.frame_sum(candidate + i);
+                correlation += value * reference;
+                energy += value * value;
+            }
+            let score = correlation / (energy.sqrt() + 1e-9);
+            if score > best.0 {
+                best = (score, candidate);
+            }
+        }
+        best.1
+    }
+}
+
+impl<S: Source> Iterator for TimeStretch<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.span.pop_front()?;
+
+        self.span_channel += 1;
+        if self.span_channel == self.span_channels as usize {
+            self.span_channel = 0;
+            self.seconds += self.span_step;
+            if self.input_done {
+                // The last segment is padded out; don't count past the input
+                let end = self.start_seconds + self.buffer_end() as f64 / self.input_rate as f64;
+                self.seconds = self.seconds.min(end);
+            }
+            self.position.store(self.seconds.to_bits(), Ordering::Relaxed);
+        }
+
+        // Always have the next span ready, so its format can be asked for
+        if self.span.is_empty() {
+            self.fill_span();
+        }
+        Some(sample)
+    }
+}
+
+impl<S: Source> Source for TimeStretch<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        Some(self.span.len())
+    }
+
+    fn channels(&self) -> rodio::ChannelCount {
+        self.span_channels
+    }
+
+    fn sample_rate(&self) -> rodio::SampleRate {
+        self.span_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)?;
+        self.input_done = false;
+        self.seconds = pos.as_secs_f64();
+        self.reset();
+        self.span.clear();
+        self.position.store(self.seconds.to_bits(), Ordering::Relaxed);
+        self.fill_span();
+        Ok(())
+    }
+}
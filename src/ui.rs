@@ -1,7 +1,171 @@
+use std::collections::HashMap;
 use std::path::PathBuf;
 use eframe::egui;
-use catppuccin_egui::{set_theme, MOCHA, LATTE};
+use catppuccin_egui::{set_theme, Theme, MOCHA, LATTE};
 use std::time::Duration;
+use crate::abloop::AbLoop;
+use crate::artwork::ArtworkLoader;
+use crate::browser::{Album, BrowseNode, BrowseTab, FolderNode, LibraryIndex};
+use crate::equalizer::{EqPreset, EqResponse, FilterKind, ParametricBand, GRAPHIC_FREQUENCIES, MAX_BAND_GAIN};
+use crate::fade::MAX_FADE_MS;
+use crate::history::{History, PlaySource};
+use crate::keybindings::{Action, KeyBinding};
+use crate::library::TrackId;
+use crate::output::OutputTarget;
+use crate::queue::{Advance, PlayQueue};
+use crate::replaygain::ReplayGainMode;
+use crate::sleep::{SleepMode, SleepTimer};
+use crate::smart_playlist::{FieldKind, Rule, RuleField, RuleOp, SmartOrder, SmartPlaylist};
+use crate::sort::{sort_files, sort_for_showing, SortColumn, SortKey};
+use crate::timestretch::{SpeedSettings, MAX_PITCH_SHIFT, MAX_SPEED, MIN_SPEED};
+use crate::visualizer::{Analyzer, FLOOR_DB};
+use crate::waveform::{Waveform, WaveformLoader};
+
+// How far the seek and volume shortcuts move things
+const SEEK_STEP: Duration = Duration::from_secs(5);
+const VOLUME_STEP: f32 = 0.05;
+
+// Quick picks in the sleep timer menu
+const SLEEP_PRESETS_MINUTES: [u64; 5] = [15, 30, 45, 60, 90];
+
+// Range of the EQ curve display, in dB either side of flat
+const EQ_CURVE_RANGE: f32 = 18.0;
+const EQ_CURVE_HEIGHT: f32 = 120.0;
+
+const VISUALIZER_HEIGHT: f32 = 80.0;
+const LEVEL_METER_WIDTH: f32 = 16.0;
+
+const SEEKBAR_HEIGHT: f32 = 48.0;
+
+const ALBUM_TILE_SIZE: f32 = 110.0;
+const ALBUM_HEADER_ART_SIZE: f32 = 32.0;
+
+// Plays listed under "Recently played" in the sidebar
+const RECENTLY_PLAYED_COUNT: usize = 20;
+
+// Anything shorter than this at the end of a track is more likely a decode
+// failure than a real playthrough, so it isn't trusted as a duration.
+const MIN_PLAYED_DURATION: Duration = Duration::from_secs(1);
+
+/// Optional track list columns, shown next to the always-visible title and duration.
+#[derive(Clone, Copy, PartialEq)]
+enum TrackColumn {
+    Artist,
+    Album,
+    TrackNumber,
+    DateAdded,
+    Path,
+    Codec,
+    SampleRate,
+    BitDepth,
+    Channels,
+    Bitrate,
+    PlayCount,
+    SkipCount,
+    LastPlayed,
+    Rating,
+}
+
+impl TrackColumn {
+    const ALL: [TrackColumn; 14] = [
+        TrackColumn::Artist,
+        TrackColumn::Album,
+        TrackColumn::TrackNumber,
+        TrackColumn::DateAdded,
+        TrackColumn::Path,
+        TrackColumn::Codec,
+        TrackColumn::SampleRate,
+        TrackColumn::BitDepth,
+        TrackColumn::Channels,
+        TrackColumn::Bitrate,
+        TrackColumn::PlayCount,
+        TrackColumn::SkipCount,
+        TrackColumn::LastPlayed,
+        TrackColumn::Rating,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            TrackColumn::Artist => "Artist",
+            TrackColumn::Album => "Album",
+            TrackColumn::TrackNumber => "#",
+            TrackColumn::DateAdded => "Date Added",
+            TrackColumn::Path => "Path",
+            TrackColumn::Codec => "Codec",
+            TrackColumn::SampleRate => "Sample Rate",
+            TrackColumn::BitDepth => "Bit Depth",
+            TrackColumn::Channels => "Channels",
+            TrackColumn::Bitrate => "Bitrate",
+            TrackColumn::PlayCount => "Plays",
+            TrackColumn::SkipCount => "Skips",
+            TrackColumn::LastPlayed => "Last Played",
+            TrackColumn::Rating => "Rating",
+        }
+    }
+
+    fn value(self, audio_file: &crate::audio::AudioFile) -> String {
+        let info = &audio_file.info;
+        let value = match self {
+            TrackColumn::Artist => audio_file.tags.artist.clone(),
+            TrackColumn::Album => audio_file.tags.album.clone(),
+            TrackColumn::TrackNumber => audio_file.tags.track_number.map(|track| match audio_file.tags.disc_number {
+                Some(disc) => format!("{}.{:02}", disc, track),
+                None => track.to_string(),
+            }),
+            TrackColumn::DateAdded => audio_file.added.map(format_date),
+            TrackColumn::Path => Some(audio_file.path.display().to_string()),
+            TrackColumn::Codec => info.codec.clone(),
+            TrackColumn::SampleRate => info.sample_rate.map(format_sample_rate),
+            TrackColumn::BitDepth => info.bits_per_sample.map(|bits| format!("{} bit", bits)),
+            TrackColumn::Channels => info.channel_layout(),
+            TrackColumn::Bitrate => info.bitrate.map(|kbps| format!("{} kbps", kbps)),
+            TrackColumn::PlayCount => Some(audio_file.stats.play_count.to_string()),
+            TrackColumn::SkipCount => Some(audio_file.stats.skip_count.to_string()),
+            TrackColumn::LastPlayed => audio_file.stats.last_played.map(format_date_time),
+            TrackColumn::Rating => Some(format_rating(audio_file.stats.rating)),
+        };
+        value.unwrap_or_else(|| "—".to_string())
+    }
+
+    fn sort_column(self) -> Option<SortColumn> {
+        match self {
+            TrackColumn::Artist => Some(SortColumn::Artist),
+            TrackColumn::Album => Some(SortColumn::Album),
+            TrackColumn::TrackNumber => Some(SortColumn::TrackNumber),
+            TrackColumn::DateAdded => Some(SortColumn::DateAdded),
+            TrackColumn::Path => Some(SortColumn::Path),
+            TrackColumn::PlayCount => Some(SortColumn::PlayCount),
+            TrackColumn::SkipCount => Some(SortColumn::SkipCount),
+            TrackColumn::LastPlayed => Some(SortColumn::LastPlayed),
+            TrackColumn::Rating => Some(SortColumn::Rating),
+            _ => None,
+        }
+    }
+}
+
+// What files dropped on the window go to; anywhere else they're played
+#[derive(Clone)]
+enum DropTarget {
+    TrackList,
+    Playlist(String),
+}
+
+impl DropTarget {
+    fn hint(&self) -> String {
+        match self {
+            DropTarget::TrackList => "Add to the playlist".to_string(),
+            DropTarget::Playlist(name) => format!("Add to '{}'", name),
+        }
+    }
+}
+
+// A smart playlist being made or changed in the rule editor
+struct SmartPlaylistEditor {
+    name: String,
+    // Name it was saved under, if it's not new
+    original_name: Option<String>,
+    playlist: SmartPlaylist,
+}
 
 pub struct AudioPlayerApp {
     playlist: Option<crate::playlist::Playlist>,  // Instead of audio_files + current_index + durations
@@ -10,37 +174,212 @@ pub struct AudioPlayerApp {
     is_dark_theme: bool,
     folder_path: String,
     volume: f32,
+    library: crate::library::Library,
+    visible_columns: Vec<TrackColumn>,
+    selected_track: Option<usize>,
+    show_properties: bool,
+    settings: crate::settings::Settings,
+    show_settings: bool,
+    show_equalizer: bool,
+    show_keybindings: bool,
+    // Action waiting for its new key in the shortcuts window
+    capturing_binding: Option<Action>,
+    search_query: String,
+    // Search every file in the library folder rather than the shown playlist
+    search_library: bool,
+    library_files: Vec<crate::audio::AudioFile>,
+    library_index: LibraryIndex,
+    show_browser: bool,
+    browse_tab: BrowseTab,
+    browse_selection: Option<BrowseNode>,
+    artwork_loader: ArtworkLoader,
+    // Artwork by the track it was loaded for; None once it's known there is none
+    artwork: HashMap<PathBuf, Option<egui::TextureHandle>>,
+    queue: PlayQueue,
+    // Track taken off the queue, playing in place of the playlist's current one
+    queued_track: Option<crate::audio::AudioFile>,
+    show_queue: bool,
+    history: History,
+    show_clear_history: bool,
+    // Days to clear history between, inclusive, as YYYY-MM-DD
+    clear_history_from: String,
+    clear_history_to: String,
+    focus_search: bool,
+    new_preset_name: String,
+    // Equalizer curve, kept until the settings or sample rate change
+    eq_response: Option<EqResponse>,
+    loudness_analyzer: crate::loudness::LoudnessAnalyzer,
+    // Tracks whose analysis or tagging failed this session, with why, so
+    // they aren't tried again on every playlist load
+    loudness_failures: HashMap<PathBuf, String>,
+    duration_scanner: crate::audio::DurationScanner,
+    waveform_loader: WaveformLoader,
+    // Waveform of the current track, once loaded, and the file it belongs to
+    waveform: Option<(PathBuf, Waveform)>,
+    output_devices: Vec<String>,
+    error_message: Option<String>,
+    loop_a: Option<Duration>,
+    loop_b: Option<Duration>,
+    // Times to repeat the A-B section, 0 for forever
+    loop_repeats: u32,
+    sleep_timer: Option<SleepTimer>,
+    sleep_minutes: u64,
+    // Track that's playing but hasn't played long enough to count as a play yet
+    uncounted_play: Option<TrackId>,
+    analyzer: Analyzer,
+    // Reused between frames for what comes out of the player's sample tap
+    tap_samples: Vec<f32>,
 
     playlist_manager: crate::playlist_manager::PlaylistManager,
     playlist_names: Vec<String>,
     selected_playlist: Option<String>,
+    smart_playlist_names: Vec<String>,
+    smart_editor: Option<SmartPlaylistEditor>,
+    // Where dropped files can land, as laid out this frame
+    drop_targets: Vec<(egui::Rect, DropTarget)>,
     show_create_dialog: bool,
     new_playlist_name: String,
 }
 
-impl Default for AudioPlayerApp {
-    fn default() -> Self {
+impl AudioPlayerApp {
+    pub fn new(args: crate::cli::Args) -> Self {
+        let folder_path = args.library.as_ref().map_or_else(|| "./tmp/audio".to_string(), |dir| dir.display().to_string());
         let mut app = Self {
-            folder_path: "./tmp/audio".to_string(),
+            folder_path,
             playlist: None,  // Changed
             volume: 1.0, 
             is_playing: false,
             player: None,
             is_dark_theme: true,
+            library: crate::library::Library::new(),
+            visible_columns: Vec::new(),
+            selected_track: None,
+            show_properties: false,
+            settings: crate::settings::Settings::load(),
+            show_settings: false,
+            show_equalizer: false,
+            show_keybindings: false,
+            capturing_binding: None,
+            search_query: String::new(),
+            search_library: false,
+            library_files: Vec::new(),
+            library_index: LibraryIndex::default(),
+            show_browser: false,
+            browse_tab: BrowseTab::Artists,
+            browse_selection: None,
+            artwork_loader: ArtworkLoader::new(),
+            artwork: HashMap::new(),
+            queue: PlayQueue::new(),
+            queued_track: None,
+            show_queue: false,
+            history: History::new(),
+            show_clear_history: false,
+            clear_history_from: String::new(),
+            clear_history_to: String::new(),
+            focus_search: false,
+            new_preset_name: String::new(),
+            eq_response: None,
+            loudness_analyzer: crate::loudness::LoudnessAnalyzer::new(),
+            loudness_failures: HashMap::new(),
+            duration_scanner: crate::audio::DurationScanner::new(),
+            waveform_loader: WaveformLoader::new(),
+            waveform: None,
+            output_devices: Vec::new(),
+            error_message: None,
+            loop_a: None,
+            loop_b: None,
+            loop_repeats: 0,
+            sleep_timer: None,
+            sleep_minutes: 30,
+            uncounted_play: None,
+            analyzer: Analyzer::new(),
+            tap_samples: Vec::new(),
             playlist_manager: crate::playlist_manager::PlaylistManager::new(),
             playlist_names: Vec::new(),
             selected_playlist: None,
+            smart_playlist_names: Vec::new(),
+            smart_editor: None,
+            drop_targets: Vec::new(),
             show_create_dialog: false,
             new_playlist_name: String::new(),
         };
 
-        if let Ok(names) = app.playlist_manager.scan_playlists() {
-            app.playlist_names = names;
-        }
+        app.refresh_playlists();
         
         app.load_files();
+        app.queue.restore(&app.library_files);
+        app.library.apply(app.queue.tracks_mut());
+        app.open_from_args(&args);
         app
     }
+
+    // Loads what was named on the command line as the playlist
+    fn open_from_args(&mut self, args: &crate::cli::Args) {
+        if let Some(volume) = args.volume {
+            self.volume = volume as f32 / 100.0;
+        }
+
+        let mut opened = false;
+        if !args.paths.is_empty() {
+            let mut files = Vec::new();
+            let mut problems = Vec::new();
+            for path in &args.paths {
+                let entries = if crate::playlist_manager::is_m3u(path) {
+                    match crate::playlist_manager::read_m3u(path) {
+                        Ok(entries) => entries,
+                        Err(e) => {
+                            problems.push(format!("Can't read playlist {}: {}", path.display(), e));
+                            continue;
+                        }
+                    }
+                } else {
+                    vec![path.clone()]
+                };
+                for entry in entries {
+                    match crate::audio::find_audio_files(&entry) {
+                        Ok(found) if found.is_empty() => problems.push(format!("No audio files in {}", entry.display())),
+                        Ok(found) => files.extend(found),
+                        Err(e) => problems.push(format!("Can't open {}: {}", entry.display(), e)),
+                    }
+                }
+            }
+            if files.is_empty() {
+                problems.insert(0, "Nothing given on the command line could be played".to_string());
+            }
+            if !problems.is_empty() {
+                self.error_message = Some(problems.join("; "));
+            }
+            if !files.is_empty() {
+                // Given order is kept, so an M3U plays as written
+                self.library.apply(&mut files);
+                self.playlist = Some(crate::playlist::Playlist::new(files));
+                self.selected_track = None;
+                self.queue_loudness_analysis();
+                self.queue_duration_scans();
+                opened = true;
+            }
+        } else if let Some(name) = &args.playlist {
+            if self.playlist_names.contains(name) {
+                self.selected_playlist = Some(name.clone());
+                self.load_playlist_songs(name);
+                opened = true;
+            } else if self.smart_playlist_names.contains(name) {
+                self.selected_playlist = Some(name.clone());
+                self.load_smart_playlist_songs(name);
+                opened = true;
+            } else {
+                self.error_message = Some(format!("There's no playlist called '{}'", name));
+            }
+        }
+
+        // Without --shuffle, whatever shuffle the playlist opened with is left alone
+        if args.shuffle && let Some(playlist) = &mut self.playlist {
+            playlist.start_shuffled();
+        }
+        if opened && !args.no_autoplay {
+            self.play_current();
+        }
+    }
 }
 
 impl eframe::App for AudioPlayerApp {
@@ -52,10 +391,45 @@ impl eframe::App for AudioPlayerApp {
             set_theme(ctx, LATTE);
         }
 
-        if self.is_playing {
-            if let Some(player) = &self.player {
-                if player.is_empty() {
-                    self.play_next();
+        if let Some(player) = &mut self.player {
+            player.update();
+            if player.take_loop_failure() {
+                self.loop_a = None;
+                self.loop_b = None;
+                self.error_message = Some("This track can't seek, so it can't loop".to_string());
+            }
+        }
+        self.advance_if_finished();
+        self.update_sleep_timer();
+        self.collect_loudness_results();
+        self.collect_duration_scans();
+        self.collect_waveforms();
+        self.collect_artwork(ctx);
+        self.count_play();
+        self.drop_targets.clear();
+        self.update_visualizer(ctx);
+        self.handle_shortcuts(ctx);
+
+        if let Some(player) = &self.player
+            && let Some(warning) = player.take_output_warning()
+        {
+            self.error_message = Some(format!("Audio output: {}", warning));
+        }
+
+        // The chosen output stopped working; carry on with the default device.
+        // If that can't be opened either, let go of the player so pressing
+        // play tries again, rather than retrying every frame.
+        if let Some(player) = &mut self.player
+            && player.is_output_lost()
+        {
+            match player.set_output(&OutputTarget::default()) {
+                Ok(()) => {
+                    self.error_message = Some("Output device disconnected, switched to the default device".to_string());
+                }
+                Err(e) => {
+                    self.error_message = Some(format!("Output device disconnected: {}", e));
+                    self.player = None;
+                    self.is_playing = false;
                 }
             }
         }
@@ -72,6 +446,13 @@ impl eframe::App for AudioPlayerApp {
                 if ui.button("➕ Create Playlist").clicked() {
                     self.show_create_dialog = true;
                 }
+                if ui.button("✨ New Smart Playlist").clicked() {
+                    self.smart_editor = Some(SmartPlaylistEditor {
+                        name: String::new(),
+                        original_name: None,
+                        playlist: SmartPlaylist::default(),
+                    });
+                }
                 
                 ui.separator();
                 
@@ -92,14 +473,276 @@ impl eframe::App for AudioPlayerApp {
                     if response.clicked() {
                         clicked_playlist = Some(playlist_name.clone());
                     }
+                    self.drop_targets.push((response.rect, DropTarget::Playlist(playlist_name.clone())));
                 }
                 
+                // Smart playlists, below the folder ones
+                let (mut clicked_smart, mut edit_smart, mut delete_smart) = (None, None, None);
+                for playlist_name in &self.smart_playlist_names {
+                    let is_selected = self.selected_playlist.as_ref() == Some(playlist_name);
+                    let response = ui.selectable_label(is_selected, format!("✨ {}", playlist_name));
+                    if response.clicked() {
+                        clicked_smart = Some(playlist_name.clone());
+                    }
+                    response.context_menu(|ui| {
+                        if ui.button("Edit rules…").clicked() {
+                            edit_smart = Some(playlist_name.clone());
+                            ui.close();
+                        }
+                        if ui.button("Delete").clicked() {
+                            delete_smart = Some(playlist_name.clone());
+                            ui.close();
+                        }
+                    });
+                }
+
                 // Process click outside the loop to avoid borrowing issues
                 if let Some(name) = clicked_playlist {
                     self.selected_playlist = Some(name.clone());
                     self.load_playlist_songs(&name);
                 }
+                if let Some(name) = clicked_smart {
+                    self.selected_playlist = Some(name.clone());
+                    self.load_smart_playlist_songs(&name);
+                }
+                if let Some(name) = edit_smart {
+                    self.edit_smart_playlist(&name);
+                }
+                if let Some(name) = delete_smart {
+                    if let Err(e) = self.playlist_manager.delete_smart_playlist(&name) {
+                        self.error_message = Some(format!("Can't delete smart playlist {}: {}", name, e));
+                    }
+                    if self.selected_playlist.as_ref() == Some(&name) {
+                        self.selected_playlist = None;
+                    }
+                    self.refresh_playlists();
+                }
+
+                ui.separator();
+                let mut replay = None;
+                egui::CollapsingHeader::new("🕘 Recently played").default_open(true).show(ui, |ui| {
+                    if self.history.entries().is_empty() {
+                        ui.weak("Nothing yet");
+                    }
+                    for entry in self.history.entries().iter().rev().take(RECENTLY_PLAYED_COUNT) {
+                        let mut details = format!(
+                            "{}\nFrom {}\nPlayed {}",
+                            format_date_time(entry.started),
+                            entry.source.label(),
+                            format_duration(entry.played)
+                        );
+                        if entry.finished {
+                            details.push_str(" (to the end)");
+                        }
+                        let label = egui::Label::new(&entry.title).truncate().sense(egui::Sense::click());
+                        if ui.add(label).on_hover_text(details).double_clicked() {
+                            replay = Some(entry.path.clone());
+                        }
+                    }
+                    if ui.button("Clear history…").clicked() {
+                        let today = format_date(std::time::SystemTime::now());
+                        self.clear_history_from = today.clone();
+                        self.clear_history_to = today;
+                        self.show_clear_history = true;
+                    }
+                });
+                if let Some(path) = replay {
+                    self.replay(path);
+                }
             });
+
+        // Clear History Dialog
+        if self.show_clear_history {
+            let mut clear = None;
+            egui::Window::new("Clear History")
+                .open(&mut self.show_clear_history)
+                .resizable(false)
+                .collapsible(false)
+                .show(ctx, |ui| {
+                    ui.horizontal(|ui| {
+                        let now = std::time::SystemTime::now();
+                        if ui.button("Today (UTC)").clicked() {
+                            self.clear_history_from = format_date(now);
+                            self.clear_history_to = format_date(now);
+                        }
+                        if ui.button("Last 7 days").clicked() {
+                            self.clear_history_from = format_date(now - Duration::from_secs(6 * 86_400));
+                            self.clear_history_to = format_date(now);
+                        }
+                        if ui.button("Everything").clicked() {
+                            self.clear_history_from = "1970-01-01".to_string();
+                            self.clear_history_to = format_date(now);
+                        }
+                    });
+                    // Play times are kept and compared in UTC, whatever the local time zone
+                    egui::Grid::new("clear_history_range").num_columns(2).show(ui, |ui| {
+                        ui.label("From (UTC):");
+                        ui.text_edit_singleline(&mut self.clear_history_from);
+                        ui.end_row();
+                        ui.label("To (UTC):");
+                        ui.text_edit_singleline(&mut self.clear_history_to);
+                        ui.end_row();
+                    });
+
+                    // The end date counts in full
+                    let range = parse_date(&self.clear_history_from)
+                        .zip(parse_date(&self.clear_history_to).map(|to| to + Duration::from_secs(86_400)));
+                    match range {
+                        Some((from, to)) => {
+                            let count = self.history.count_range(from, to);
+                            ui.label(format!("{} plays in this range", count));
+                            if ui.add_enabled(count > 0, egui::Button::new("Clear")).clicked() {
+                                clear = Some((from, to));
+                            }
+                        }
+                        None => {
+                            ui.colored_label(egui::Color32::RED, "Dates are written YYYY-MM-DD");
+                        }
+                    }
+                });
+
+            if let Some((from, to)) = clear {
+                self.history.clear_range(from, to);
+                self.save_history();
+                self.show_clear_history = false;
+            }
+        }
+
+        // Library browser
+        if self.show_browser {
+            let mut chosen = None;
+            let mut wanted_artwork = Vec::new();
+            egui::SidePanel::left("library_browser")
+                .resizable(true)
+                .default_width(240.0)
+                .show(ctx, |ui| {
+                    ui.heading("Library");
+                    ui.horizontal_wrapped(|ui| {
+                        for tab in BrowseTab::ALL {
+                            ui.selectable_value(&mut self.browse_tab, tab, tab.label());
+                        }
+                    });
+                    ui.separator();
+
+                    let index = &self.library_index;
+                    let selection = self.browse_selection.as_ref();
+                    egui::ScrollArea::vertical().show(ui, |ui| match self.browse_tab {
+                        BrowseTab::Artists => {
+                            for (artist, albums) in &index.artists {
+                                egui::CollapsingHeader::new(artist).id_salt(("artist", artist)).show(ui, |ui| {
+                                    browse_item(ui, BrowseNode::Artist(artist.clone()), "All tracks".to_string(), selection, &mut chosen);
+                                    for album in albums {
+                                        browse_item(ui, album.node(), album_label(album), selection, &mut chosen);
+                                    }
+                                });
+                            }
+                        }
+                        BrowseTab::Albums => {
+                            ui.horizontal_wrapped(|ui| {
+                                for album in &index.albums {
+                                    let artwork = self.artwork.get(&album.artwork_track);
+                                    if artwork.is_none() {
+                                        wanted_artwork.push(album.artwork_track.clone());
+                                    }
+                                    let selected = selection == Some(&album.node());
+                                    if album_tile(ui, album, artwork.and_then(Option::as_ref), selected).clicked() {
+                                        chosen = Some(album.node());
+                                    }
+                                }
+                            });
+                        }
+                        BrowseTab::Genres => {
+                            for (genre, count) in &index.genres {
+                                let text = format!("{} ({})", genre, count);
+                                browse_item(ui, BrowseNode::Genre(genre.clone()), text, selection, &mut chosen);
+                            }
+                        }
+                        BrowseTab::Years => {
+                            for (year, count) in &index.years {
+                                let text = format!("{} ({})", year, count);
+                                browse_item(ui, BrowseNode::Year(*year), text, selection, &mut chosen);
+                            }
+                        }
+                        BrowseTab::Folders => {
+                            if let Some(root) = &index.folders {
+                                browse_folder(ui, root, selection, &mut chosen);
+                            }
+                        }
+                    });
+                });
+
+            for track in wanted_artwork {
+                self.artwork_loader.queue(&track);
+            }
+            if let Some(node) = chosen {
+                self.browse(node);
+            }
+        }
+
+        // Play queue
+        if self.show_queue {
+            let (mut move_up, mut move_down, mut remove, mut play) = (None, None, None, None);
+            let mut clear = false;
+            egui::SidePanel::right("play_queue")
+                .resizable(true)
+                .default_width(220.0)
+                .show(ctx, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.heading("Queue");
+                        if ui.add_enabled(!self.queue.is_empty(), egui::Button::new("Clear")).clicked() {
+                            clear = true;
+                        }
+                    });
+                    if let Some(track) = &self.queued_track {
+                        ui.label(format!("Playing: {}", track.title));
+                    }
+                    ui.separator();
+
+                    if self.queue.is_empty() {
+                        ui.weak("Right-click a track to queue it");
+                    }
+                    let last = self.queue.tracks().len().saturating_sub(1);
+                    egui::ScrollArea::vertical().show(ui, |ui| {
+                        for (index, track) in self.queue.tracks().iter().enumerate() {
+                            ui.horizontal(|ui| {
+                                if ui.add_enabled(index > 0, egui::Button::new("⏶").small()).clicked() {
+                                    move_up = Some(index);
+                                }
+                                if ui.add_enabled(index < last, egui::Button::new("⏷").small()).clicked() {
+                                    move_down = Some(index);
+                                }
+                                if ui.small_button("✖").on_hover_text("Remove from queue").clicked() {
+                                    remove = Some(index);
+                                }
+                                let label = ui.add(egui::Label::new(format!("{}. {}", index + 1, track.title)).truncate().sense(egui::Sense::click()));
+                                if label.double_clicked() {
+                                    play = Some(index);
+                                }
+                            });
+                        }
+                    });
+                });
+
+            let changed = move_up.is_some() || move_down.is_some() || remove.is_some() || clear;
+            if let Some(index) = move_up {
+                self.queue.move_track(index, index - 1);
+            }
+            if let Some(index) = move_down {
+                self.queue.move_track(index, index + 1);
+            }
+            if let Some(index) = remove {
+                self.queue.remove(index);
+            }
+            if clear {
+                self.queue.clear();
+            }
+            if changed {
+                self.save_queue();
+            }
+            if let Some(index) = play {
+                self.play_from_queue(index);
+            }
+        }
         
         // Create Playlist Dialog
         if self.show_create_dialog {
@@ -129,7 +772,7 @@ impl eframe::App for AudioPlayerApp {
                         let create_enabled = is_valid;
                         if ui.add_enabled(create_enabled, egui::Button::new("Create")).clicked() {
                             if let Err(e) = self.playlist_manager.create_playlist(&playlist_name) {
-                                eprintln!("Error creating playlist: {}", e);
+                                self.error_message = Some(format!("Can't create playlist {}: {}", playlist_name, e));
                             } else {
                                 // Success - refresh playlist list and close dialog
                                 self.refresh_playlists();
@@ -147,152 +790,1990 @@ impl eframe::App for AudioPlayerApp {
                 });
         }
         
-        // Existing CentralPanel stays the same
-        egui::CentralPanel::default().show(ctx, |ui| {
-            ui.heading("Lil Glucose Player");
-            ui.separator();
-            // Folder selection
-            ui.horizontal(|ui| {
-                let theme_text = if self.is_dark_theme { "🌙" } else { "light" };
-                if ui.button(theme_text).clicked() {
-                    self.is_dark_theme = !self.is_dark_theme;
-                }
-                // ui.label("Folder:");
-                // ui.text_edit_singleline(&mut self.folder_path);
-                // if ui.button("Load Files").clicked() {
-                //     self.load_files();
-                // }
-            });
+        // Smart Playlist Rule Editor
+        if let Some(editor) = &mut self.smart_editor {
+            let mut open = true;
+            let (mut save, mut cancel) = (false, false);
+            egui::Window::new("Smart Playlist")
+                .open(&mut open)
+                .collapsible(false)
+                .resizable(false)
+                .show(ctx, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Name:");
+                        ui.text_edit_singleline(&mut editor.name);
+                    });
+                    let playlist = &mut editor.playlist;
 
-            ui.separator();
+                    ui.horizontal(|ui| {
+                        ui.label("Match");
+                        egui::ComboBox::from_id_salt("smart_match")
+                            .selected_text(if playlist.match_all { "all" } else { "any" })
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut playlist.match_all, true, "all");
+                                ui.selectable_value(&mut playlist.match_all, false, "any");
+                            });
+                        ui.label("of these rules:");
+                    });
 
-            let file_count = self.playlist.as_ref().map(|p| p.len()).unwrap_or(0);
-            ui.label(format!("Found {} files", file_count));
+                    let mut remove_rule = None;
+                    for (i, rule) in playlist.rules.iter_mut().enumerate() {
+                        ui.horizontal(|ui| {
+                            let mut field = rule.field;
+                            egui::ComboBox::from_id_salt(("smart_field", i))
+                                .selected_text(field.label())
+                                .show_ui(ui, |ui| {
+                                    for option in RuleField::ALL {
+                                        ui.selectable_value(&mut field, option, option.label());
+                                    }
+                                });
+                            if field != rule.field {
+                                rule.set_field(field);
+                            }
 
-            ui.separator(); 
-            
-            // Play/Pause controls
-            ui.horizontal(|ui| {
-                // Previous Button
-                if ui.button("⏮").clicked() {
-                    self.play_previous();
-                }
-                // Play/Pause button
-                if ui.button(if self.is_playing { "⏸" } else { "▶" }).clicked() {
-                    self.toggle_play_pause();
-                }
-                // Next Button
-                if ui.button("⏭").clicked() {
-                    self.play_next();
-                }
-            });
-            
+                            egui::ComboBox::from_id_salt(("smart_op", i))
+                                .selected_text(rule.op.label())
+                                .show_ui(ui, |ui| {
+                                    for &op in RuleOp::for_kind(rule.field.kind()) {
+                                        ui.selectable_value(&mut rule.op, op, op.label());
+                                    }
+                                });
 
-            if let Some(playlist) = &self.playlist {
-                if let Some(audio_file) = playlist.current() {
-                    ui.label(format!("Now: {}", audio_file.title));
-                    if let Some(player) = &self.player {
-                        let current_pos = player.get_position();
-                        let total_duration = audio_file.duration.unwrap_or(Duration::ZERO);
-    
-                        let mut progress = if total_duration.as_secs() > 0 {
-                            current_pos.as_secs_f64() / total_duration.as_secs_f64()
-                        } else {
-                            0.0
-                        };
-                        progress = progress.min(1.0);
-                        // Display slider (read-only for now)
-                        ui.add(
-                            egui::Slider::new(&mut progress, 0.0..=1.0)
-                                .show_value(false)  // Don't show the 0.0-1.0 number
-                        );
-                        
-                        // Show time below slider: "1:23 / 3:45"
-                        ui.label(format!(
-                            "{} / {}", 
-                            format_duration(current_pos),
-                            format_duration(total_duration)
-                        ));
+                            match rule.field.kind() {
+                                FieldKind::Text => {
+                                    ui.add(egui::TextEdit::singleline(&mut rule.text).desired_width(140.0));
+                                }
+                                FieldKind::Number if rule.field == RuleField::Rating => {
+                                    ui.add(egui::DragValue::new(&mut rule.number).range(0..=5).suffix(" ★"));
+                                }
+                                FieldKind::Number => {
+                                    ui.add(egui::DragValue::new(&mut rule.number));
+                                }
+                                FieldKind::Date => {
+                                    ui.add(egui::DragValue::new(&mut rule.number).suffix(" days"));
+                                }
+                            }
+
+                            if ui.small_button("✖").on_hover_text("Remove rule").clicked() {
+                                remove_rule = Some(i);
+                            }
+                        });
+                    }
+                    if let Some(i) = remove_rule {
+                        playlist.rules.remove(i);
+                    }
+                    if ui.button("➕ Add rule").clicked() {
+                        playlist.rules.push(Rule::new(RuleField::Genre));
                     }
-                }
-            }
-        });
-        ctx.request_repaint();
-    }
-}
 
-impl AudioPlayerApp {
-    fn load_files(&mut self) {
-        let path = PathBuf::from("./tmp/audio");
-        match crate::audio::find_audio_files(&path) {
-            Ok(files) => {
-                self.playlist = Some(crate::playlist::Playlist::new(files));
-            }
-            Err(e) => {
-                eprintln!("error loading files {}", e);
-            }
-        }
-    }
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        let mut limited = playlist.limit.is_some();
+                        if ui.checkbox(&mut limited, "Limit to").changed() {
+                            playlist.limit = limited.then_some(50);
+                        }
+                        if let Some(limit) = &mut playlist.limit {
+                            ui.add(egui::DragValue::new(limit).range(1..=100_000).suffix(" tracks"));
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Ordered by");
+                        egui::ComboBox::from_id_salt("smart_order")
+                            .selected_text(playlist.order.label())
+                            .show_ui(ui, |ui| {
+                                for order in SmartOrder::ALL {
+                                    ui.selectable_value(&mut playlist.order, order, order.label());
+                                }
+                            });
+                    });
+                    ui.label(format!("{} tracks match", playlist.count(&self.library_files)));
 
-    fn toggle_play_pause(&mut self) {
-        if self.player.is_none() {
-            if let Ok(mut player) = crate::player::Player::new(){
-                if let Some(playlist) = &self.playlist {
-                    if let Some(audio_file) = playlist.current() {
-                        let _ = player.play(&audio_file.path);
-                        self.is_playing = true;
-                        self.player = Some(player);
+                    ui.add_space(10.0);
+                    let name = editor.name.trim();
+                    let taken = self.playlist_names.iter().chain(&self.smart_playlist_names)
+                        .any(|existing| existing == name && editor.original_name.as_deref() != Some(name));
+                    let is_valid = !name.is_empty()
+                        && !name.chars().any(|c| matches!(c, '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|'))
+                        && !taken;
+                    if taken {
+                        ui.label(egui::RichText::new("A playlist with this name already exists").color(egui::Color32::RED));
                     }
-                }
-            }
-        } else if let Some(player) = &mut self.player {
-            if self.is_playing{
-                player.pause();
-                self.is_playing = false;
-            } else {
-                player.resume();
-                self.is_playing = true;
-            }
-        }
-    }
+                    ui.horizontal(|ui| {
+                        if ui.add_enabled(is_valid, egui::Button::new("Save")).clicked() {
+                            save = true;
+                        }
+                        if ui.button("Cancel").clicked() {
+                            cancel = true;
+                        }
+                    });
+                });
 
-    pub fn play_next(&mut self) {
-        if let Some(playlist) = &mut self.playlist {
-            if let Some(next_file) = playlist.next() {
-                if let Some(player) = &mut self.player {
-                    let _ = player.play(&next_file.path);
+            if save {
+                if let Some(editor) = self.smart_editor.take() {
+                    self.save_smart_playlist(editor);
                 }
-            } else {
-                // Reached end
-                self.is_playing = false;
+            } else if cancel || !open {
+                self.smart_editor = None;
             }
         }
-    }
-
-    pub fn play_previous(&mut self) {
-        if let Some(playlist) = &mut self.playlist {
-            if let Some(previous_file) = playlist.previous() {
-                if let Some(player) = &mut self.player {
-                    let _ = player.play(&previous_file.path);
-                }
-            } 
-        }
-    }
 
-    fn refresh_playlists(&mut self) {
-        if let Ok(names) = self.playlist_manager.scan_playlists() {
-            self.playlist_names = names;
-        }
-    }
+        // Track Properties panel
+        if self.show_properties {
+            let selected = self.selected_track
+                .or_else(|| self.playlist.as_ref().and_then(|p| p.current_index()));
+            let audio_file = self.playlist.as_ref()
+                .and_then(|p| selected.and_then(|i| p.all_files().get(i)));
+
+            egui::Window::new("Track Properties")
+                .open(&mut self.show_properties)
+                .resizable(false)
+                .show(ctx, |ui| {
+                    let Some(audio_file) = audio_file else {
+                        ui.label("No track selected");
+                        return;
+                    };
+                    let info = &audio_file.info;
+                    let unknown = || "—".to_string();
+
+                    egui::Grid::new("track_properties").num_columns(2).show(ui, |ui| {
+                        ui.label("Title:");
+                        ui.label(&audio_file.title);
+                        ui.end_row();
+
+                        ui.label("Path:");
+                        ui.label(audio_file.path.display().to_string());
+                        ui.end_row();
+
+                        ui.label("Duration:");
+                        ui.label(format_track_duration(audio_file));
+                        ui.end_row();
+
+                        ui.label("Codec:");
+                        ui.label(info.codec_description.clone().unwrap_or_else(unknown));
+                        ui.end_row();
+
+                        for column in [TrackColumn::SampleRate, TrackColumn::BitDepth, TrackColumn::Channels, TrackColumn::Bitrate] {
+                            ui.label(format!("{}:", column.label()));
+                            ui.label(column.value(audio_file));
+                            ui.end_row();
+                        }
+
+                        let replay_gain = &audio_file.replay_gain;
+                        let format_gain = |gain: Option<f32>, peak: Option<f32>| match (gain, peak) {
+                            (Some(gain), Some(peak)) => format!("{:+.2} dB (peak {:.3})", gain, peak),
+                            (Some(gain), None) => format!("{:+.2} dB", gain),
+                            _ => unknown(),
+                        };
+
+                        ui.label("Track Gain:");
+                        ui.label(format_gain(replay_gain.track_gain, replay_gain.track_peak));
+                        ui.end_row();
+
+                        ui.label("Album Gain:");
+                        ui.label(format_gain(replay_gain.album_gain, replay_gain.album_peak));
+                        ui.end_row();
+
+                        if let Some(loudness) = self.library.loudness(&audio_file.path) {
+                            ui.label("Loudness:");
+                            ui.label(format!(
+                                "{:.1} LUFS, range {:.1} LU, true peak {:.1} dBTP",
+                                loudness.integrated,
+                                loudness.range,
+                                20.0 * loudness.true_peak.log10()
+                            ));
+                            ui.end_row();
+                        }
+                    });
+                });
+        }
+
+        // Settings window
+        if self.show_settings {
+            let mut settings_changed = false;
+            let mut output_changed = false;
+            let mut stereo_changed = false;
+            let mut fade_changed = false;
+            let mut visualizer_changed = false;
+            let mut refresh_devices = false;
+            egui::Window::new("Settings")
+                .open(&mut self.show_settings)
+                .resizable(false)
+                .show(ctx, |ui| {
+                    ui.heading("Output");
+                    let output = &mut self.settings.output;
+
+                    egui::ComboBox::from_id_salt("output_kind")
+                        .selected_text(output.kind_label())
+                        .show_ui(ui, |ui| {
+                            for kind in OutputTarget::kinds() {
+                                let selected = output.is_same_kind(&kind);
+                                if ui.selectable_label(selected, kind.kind_label()).clicked() && !selected {
+                                    *output = kind;
+                                    output_changed = true;
+                                }
+                            }
+                        });
+
+                    match output {
+                        OutputTarget::Device(output_device) => {
+                            ui.horizontal(|ui| {
+                                egui::ComboBox::from_id_salt("output_device")
+                                    .selected_text(output_device.as_deref().unwrap_or("System default"))
+                                    .show_ui(ui, |ui| {
+                                        output_changed |= ui
+                                            .selectable_value(output_device, None, "System default")
+                                            .changed();
+                                        for name in &self.output_devices {
+                                            output_changed |= ui
+                                                .selectable_value(output_device, Some(name.clone()), name)
+                                                .changed();
+                                        }
+                                    });
+                                refresh_devices = ui.button("🔄").on_hover_text("Refresh devices").clicked();
+                            });
+                        }
+                        OutputTarget::WavFile(path) => {
+                            ui.horizontal(|ui| {
+                                ui.label("File:");
+                                let mut text = path.display().to_string();
+                                let response = ui.text_edit_singleline(&mut text);
+                                if response.changed() {
+                                    *path = PathBuf::from(text);
+                                }
+                                // Reopen once editing is done, not on every keystroke
+                                output_changed |= response.lost_focus();
+                            });
+                        }
+                        OutputTarget::Null { speed } => {
+                            let response = ui.add(
+                                egui::Slider::new(speed, 1.0..=100.0)
+                                    .logarithmic(true)
+                                    .text("Speed")
+                                    .suffix("x"),
+                            );
+                            output_changed |= response.drag_stopped() || (response.changed() && !response.dragged());
+                        }
+                    }
+
+                    ui.separator();
+                    ui.heading("Channels");
+                    let stereo = &mut self.settings.stereo;
+
+                    ui.horizontal(|ui| {
+                        stereo_changed |= ui
+                            .add(
+                                egui::Slider::new(&mut stereo.balance, -1.0..=1.0)
+                                    .text("Balance")
+                                    .custom_formatter(|balance, _| format_balance(balance)),
+                            )
+                            .changed();
+                        if ui.small_button("Center").clicked() {
+                            stereo.balance = 0.0;
+                            stereo_changed = true;
+                        }
+                    });
+                    stereo_changed |= ui.checkbox(&mut stereo.mono, "Mono downmix").changed();
+                    stereo_changed |= ui.checkbox(&mut stereo.swap, "Swap left and right").changed();
+
+                    ui.separator();
+                    ui.heading("Fades");
+                    fade_changed |= ui
+                        .add(
+                            egui::Slider::new(&mut self.settings.fade.duration_ms, 0..=MAX_FADE_MS)
+                                .text("Pause, stop and skip")
+                                .suffix(" ms")
+                                .custom_formatter(|ms, _| if ms == 0.0 { "Off".to_string() } else { format!("{}", ms) }),
+                        )
+                        .changed();
+
+                    ui.separator();
+                    ui.heading("Visualizer");
+                    let visualizer = &mut self.settings.visualizer;
+
+                    visualizer_changed |= ui
+                        .checkbox(&mut visualizer.enabled, "Show spectrum and level meter")
+                        .changed();
+                    ui.add_enabled_ui(visualizer.enabled, |ui| {
+                        visualizer_changed |= ui
+                            .add(egui::Slider::new(&mut visualizer.band_count, 8..=64).text("Bands"))
+                            .changed();
+                        visualizer_changed |= ui
+                            .add(egui::Slider::new(&mut visualizer.decay, 5.0..=120.0).text("Decay").suffix(" dB/s"))
+                            .changed();
+                    });
+
+                    ui.separator();
+                    ui.heading("ReplayGain");
+                    let replay_gain = &mut self.settings.replay_gain;
+
+                    egui::ComboBox::from_label("Mode")
+                        .selected_text(replay_gain.mode.label())
+                        .show_ui(ui, |ui| {
+                            for mode in ReplayGainMode::ALL {
+                                settings_changed |= ui
+                                    .selectable_value(&mut replay_gain.mode, mode, mode.label())
+                                    .changed();
+                            }
+                        });
+                    settings_changed |= ui
+                        .add(egui::Slider::new(&mut replay_gain.preamp, -15.0..=15.0).text("Preamp").suffix(" dB"))
+                        .changed();
+                    settings_changed |= ui
+                        .checkbox(&mut replay_gain.prevent_clipping, "Prevent clipping")
+                        .changed();
+
+                    ui.separator();
+                    ui.heading("Loudness Analysis");
+                    let loudness = &mut self.settings.loudness;
+
+                    settings_changed |= ui
+                        .checkbox(&mut loudness.analyze, "Analyze tracks without ReplayGain tags")
+                        .changed();
+                    settings_changed |= ui
+                        .checkbox(&mut loudness.write_tags, "Write results as ReplayGain tags")
+                        .changed();
+
+                    let pending = self.loudness_analyzer.pending_count();
+                    if pending > 0 {
+                        ui.label(format!("Analyzing… {} tracks left", pending));
+                    }
+                    if !self.loudness_failures.is_empty() {
+                        let heading = format!("⚠ {} tracks couldn't be analyzed", self.loudness_failures.len());
+                        ui.collapsing(heading, |ui| {
+                            for (path, error) in &self.loudness_failures {
+                                let name = path.file_name().unwrap_or(path.as_os_str()).to_string_lossy();
+                                ui.label(format!("{}: {}", name, error)).on_hover_text(path.display().to_string());
+                            }
+                        });
+                    }
+                });
+
+            if refresh_devices {
+                self.refresh_output_devices();
+            }
+
+            if output_changed
+                && let Some(player) = &mut self.player
+                && let Err(e) = player.set_output(&self.settings.output)
+            {
+                self.error_message = Some(format!("Can't switch output: {}", e));
+            }
+
+            if stereo_changed
+                && let Some(player) = &self.player
+            {
+                player.set_stereo(&self.settings.stereo);
+            }
+
+            if fade_changed
+                && let Some(player) = &self.player
+            {
+                player.set_fade_duration(self.settings.fade.duration());
+            }
+
+            if visualizer_changed
+                && let Some(player) = &self.player
+            {
+                player.set_tap_enabled(self.settings.visualizer.enabled);
+            }
+
+            if settings_changed || output_changed || stereo_changed || fade_changed || visualizer_changed {
+                self.apply_replay_gain();
+                self.queue_loudness_analysis();
+                self.save_settings();
+            }
+        }
+
+        // Keyboard shortcuts window
+        if self.show_keybindings {
+            let mut bindings_changed = false;
+
+            // The binding being changed takes the next key pressed; Escape cancels
+            if let Some(action) = self.capturing_binding {
+                let pressed = ctx.input_mut(|i| {
+                    let pressed = i.events.iter().find_map(|event| match event {
+                        egui::Event::Key { key, pressed: true, modifiers, .. } => Some((*key, *modifiers)),
+                        _ => None,
+                    });
+                    // So it doesn't also press the focused button
+                    if let Some((key, modifiers)) = pressed {
+                        i.consume_key(modifiers, key);
+                    }
+                    pressed
+                });
+                if let Some((key, modifiers)) = pressed {
+                    if key != egui::Key::Escape {
+                        self.settings.keybindings.set(action, KeyBinding::new(key, modifiers));
+                        bindings_changed = true;
+                    }
+                    self.capturing_binding = None;
+                }
+            }
+
+            egui::Window::new("Keyboard Shortcuts")
+                .open(&mut self.show_keybindings)
+                .resizable(false)
+                .show(ctx, |ui| {
+                    let bindings = &mut self.settings.keybindings;
+                    egui::Grid::new("keybindings").striped(true).show(ui, |ui| {
+                        for action in Action::ALL {
+                            ui.label(action.label());
+                            let text = if self.capturing_binding == Some(action) {
+                                "Press a key…".to_string()
+                            } else {
+                                ctx.format_shortcut(&bindings.get(action).shortcut())
+                            };
+                            if ui.button(text).clicked() {
+                                self.capturing_binding = Some(action);
+                            }
+                            if ui.add_enabled(bindings.is_custom(action), egui::Button::new("Reset")).clicked() {
+                                bindings.reset(action);
+                                bindings_changed = true;
+                            }
+                            let conflicts = bindings.conflicts(action);
+                            if !conflicts.is_empty() {
+                                let names: Vec<_> = conflicts.iter().map(|other| other.label()).collect();
+                                ui.colored_label(egui::Color32::RED, "⚠ Conflict")
+                                    .on_hover_text(format!("Also bound to {}", names.join(", ")));
+                            }
+                            ui.end_row();
+                        }
+                    });
+                    ui.separator();
+                    if ui.button("Reset all").clicked() {
+                        bindings.reset_all();
+                        bindings_changed = true;
+                    }
+                });
+
+            if !self.show_keybindings {
+                self.capturing_binding = None;
+            }
+            if bindings_changed {
+                self.save_settings();
+            }
+        }
+
+        // Equalizer window
+        if self.show_equalizer {
+            let mut eq_changed = false;
+            let sample_rate = self
+                .current_track()
+                .and_then(|audio_file| audio_file.info.sample_rate)
+                .unwrap_or(44_100);
+            egui::Window::new("Equalizer")
+                .open(&mut self.show_equalizer)
+                .resizable(false)
+                .show(ctx, |ui| {
+                    let eq = &mut self.settings.equalizer;
+
+                    ui.horizontal(|ui| {
+                        eq_changed |= ui.checkbox(&mut eq.enabled, "Enabled").changed();
+
+                        let mut chosen = None;
+                        let builtin = EqPreset::builtin();
+                        let current = builtin
+                            .iter()
+                            .chain(&eq.user_presets)
+                            .find(|preset| preset.gains == eq.gains)
+                            .map_or("Custom", |preset| preset.name.as_str());
+                        egui::ComboBox::from_id_salt("eq_preset")
+                            .selected_text(current)
+                            .show_ui(ui, |ui| {
+                                for preset in builtin.iter().chain(&eq.user_presets) {
+                                    if ui.selectable_label(preset.name == current, &preset.name).clicked() {
+                                        chosen = Some(preset.clone());
+                                    }
+                                }
+                            });
+                        if let Some(preset) = chosen {
+                            eq.apply_preset(&preset);
+                            eq_changed = true;
+                        }
+                    });
+
+                    if self.eq_response.as_ref().is_none_or(|response| !response.is_for(eq, sample_rate)) {
+                        self.eq_response = Some(EqResponse::new(eq, sample_rate));
+                    }
+                    if let Some(response) = &self.eq_response {
+                        draw_eq_curve(ui, response);
+                    }
+
+                    eq_changed |= ui
+                        .add(egui::Slider::new(&mut eq.preamp, -MAX_BAND_GAIN..=MAX_BAND_GAIN).text("Preamp").suffix(" dB"))
+                        .changed();
+
+                    ui.horizontal(|ui| {
+                        for (gain, frequency) in eq.gains.iter_mut().zip(GRAPHIC_FREQUENCIES) {
+                            ui.vertical(|ui| {
+                                eq_changed |= ui
+                                    .add(egui::Slider::new(gain, -MAX_BAND_GAIN..=MAX_BAND_GAIN).vertical().show_value(false))
+                                    .on_hover_text(format!("{:+.1} dB", gain))
+                                    .changed();
+                                ui.label(format_frequency(frequency));
+                            });
+                        }
+                    });
+
+                    ui.collapsing("Parametric bands", |ui| {
+                        let mut removed = None;
+                        for (i, band) in eq.parametric.iter_mut().enumerate() {
+                            ui.horizontal(|ui| {
+                                eq_changed |= ui.checkbox(&mut band.enabled, "").changed();
+                                egui::ComboBox::from_id_salt(("eq_band_kind", i))
+                                    .selected_text(band.kind.label())
+                                    .show_ui(ui, |ui| {
+                                        for kind in FilterKind::ALL {
+                                            eq_changed |= ui.selectable_value(&mut band.kind, kind, kind.label()).changed();
+                                        }
+                                    });
+                                eq_changed |= ui
+                                    .add(egui::DragValue::new(&mut band.frequency).range(20.0..=20000.0).speed(5.0).suffix(" Hz"))
+                                    .changed();
+                                eq_changed |= ui
+                                    .add(egui::DragValue::new(&mut band.gain).range(-MAX_BAND_GAIN..=MAX_BAND_GAIN).speed(0.1).suffix(" dB"))
+                                    .changed();
+                                eq_changed |= ui
+                                    .add(egui::DragValue::new(&mut band.q).range(0.1..=10.0).speed(0.01).prefix("Q "))
+                                    .changed();
+                                if ui.small_button("🗑").clicked() {
+                                    removed = Some(i);
+                                }
+                            });
+                        }
+                        if let Some(i) = removed {
+                            eq.parametric.remove(i);
+                            eq_changed = true;
+                        }
+                        if ui.button("➕ Add band").clicked() {
+                            eq.parametric.push(ParametricBand::default());
+                            eq_changed = true;
+                        }
+                    });
+
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        ui.text_edit_singleline(&mut self.new_preset_name);
+                        let name = self.new_preset_name.trim();
+                        let name_taken = eq.is_preset_name_taken(name);
+                        let mut save = ui.add_enabled(!name.is_empty() && !name_taken, egui::Button::new("💾 Save preset"));
+                        if name_taken {
+                            save = save.on_disabled_hover_text("A preset already has this name");
+                        }
+                        if save.clicked() {
+                            eq_changed |= eq.save_preset(name);
+                        }
+                        let is_user_preset = eq.user_presets.iter().any(|preset| preset.name == name);
+                        if ui.add_enabled(is_user_preset, egui::Button::new("🗑 Delete preset")).clicked() {
+                            eq.delete_preset(name);
+                            eq_changed = true;
+                        }
+                    });
+                });
+
+            if eq_changed {
+                if let Some(player) = &self.player {
+                    player.set_equalizer(&self.settings.equalizer);
+                }
+                self.save_settings();
+            }
+        }
+
+        // Existing CentralPanel stays the same
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.heading("Lil Glucose Player");
+            ui.separator();
+
+            // Last error, until dismissed
+            if let Some(message) = &self.error_message {
+                let mut dismissed = false;
+                ui.horizontal(|ui| {
+                    ui.label(egui::RichText::new(format!("⚠ {}", message)).color(egui::Color32::RED));
+                    dismissed = ui.small_button("✖").clicked();
+                });
+                if dismissed {
+                    self.error_message = None;
+                }
+                ui.separator();
+            }
+
+            // Folder selection
+            ui.horizontal(|ui| {
+                let theme_text = if self.is_dark_theme { "🌙" } else { "light" };
+                if ui.button(theme_text).clicked() {
+                    self.is_dark_theme = !self.is_dark_theme;
+                }
+                if ui.button("⚙").clicked() {
+                    self.refresh_output_devices();
+                    self.show_settings = true;
+                }
+                if ui.button("🎚").on_hover_text("Equalizer").clicked() {
+                    self.show_equalizer = true;
+                }
+                if ui.button("⌨").on_hover_text("Keyboard shortcuts").clicked() {
+                    self.show_keybindings = true;
+                }
+                if ui.selectable_label(self.show_browser, "📚").on_hover_text("Library browser").clicked() {
+                    self.show_browser = !self.show_browser;
+                }
+                if ui.selectable_label(self.show_queue, "☰").on_hover_text("Play queue").clicked() {
+                    self.show_queue = !self.show_queue;
+                }
+                // ui.label("Folder:");
+                // ui.text_edit_singleline(&mut self.folder_path);
+                // if ui.button("Load Files").clicked() {
+                //     self.load_files();
+                // }
+            });
+
+            ui.separator();
+
+            let file_count = self.playlist.as_ref().map(|p| p.len()).unwrap_or(0);
+            ui.label(format!("Found {} files", file_count));
+
+            ui.separator(); 
+            
+            // Play/Pause controls
+            ui.horizontal(|ui| {
+                // Previous Button
+                if ui.button("⏮").clicked() {
+                    self.play_previous();
+                }
+                // Play/Pause button
+                if ui.button(if self.is_playing { "⏸" } else { "▶" }).clicked() {
+                    self.toggle_play_pause();
+                }
+                // Next Button
+                if ui.button("⏭").clicked() {
+                    self.play_next();
+                }
+                // Shuffle toggle
+                let shuffle = self.playlist.as_ref().is_some_and(|p| p.is_shuffled());
+                if ui.selectable_label(shuffle, "🔀").clicked()
+                    && let Some(playlist) = &mut self.playlist
+                {
+                    playlist.set_shuffle(!shuffle);
+                    self.apply_replay_gain();
+                }
+
+                if ui.add(egui::Slider::new(&mut self.volume, 0.0..=1.0).show_value(false).text("🔊")).changed()
+                    && let Some(player) = &self.player
+                {
+                    player.set_volume(self.volume);
+                }
+
+                // Sleep timer
+                let mut sleep_choice = None;
+                let mut cancel_sleep = false;
+                ui.menu_button("💤", |ui| {
+                    for minutes in SLEEP_PRESETS_MINUTES {
+                        if ui.button(format!("{} min", minutes)).clicked() {
+                            sleep_choice = Some(SleepMode::After(Duration::from_secs(minutes * 60)));
+                            ui.close();
+                        }
+                    }
+                    ui.horizontal(|ui| {
+                        ui.add(egui::DragValue::new(&mut self.sleep_minutes).range(1..=600).suffix(" min"));
+                        if ui.button("Start").clicked() {
+                            sleep_choice = Some(SleepMode::After(Duration::from_secs(self.sleep_minutes * 60)));
+                            ui.close();
+                        }
+                    });
+                    ui.separator();
+                    for mode in [SleepMode::EndOfTrack, SleepMode::EndOfPlaylist] {
+                        if ui.add_enabled(self.is_playing, egui::Button::new(mode.label())).clicked() {
+                            sleep_choice = Some(mode);
+                            ui.close();
+                        }
+                    }
+                    if self.sleep_timer.is_some() {
+                        ui.separator();
+                        if ui.button("Cancel timer").clicked() {
+                            cancel_sleep = true;
+                            ui.close();
+                        }
+                    }
+                });
+                if let Some(timer) = &self.sleep_timer {
+                    let remaining = timer.remaining().map_or_else(|| timer.mode.label(), format_duration);
+                    ui.label(format!("💤 {}", remaining)).on_hover_text(timer.mode.label());
+                }
+
+                if let Some(mode) = sleep_choice {
+                    self.start_sleep_timer(mode);
+                }
+                if cancel_sleep {
+                    self.cancel_sleep_timer();
+                }
+            });
+
+            // Speed and pitch
+            let mut speed_changed = false;
+            ui.horizontal(|ui| {
+                let speed = &mut self.settings.speed;
+                speed_changed |= ui
+                    .add(egui::Slider::new(&mut speed.speed, MIN_SPEED..=MAX_SPEED).step_by(0.05).text("Speed").suffix("x"))
+                    .changed();
+                speed_changed |= ui.checkbox(&mut speed.keep_pitch, "Keep pitch").changed();
+                speed_changed |= ui
+                    .add(
+                        egui::Slider::new(&mut speed.semitones, -MAX_PITCH_SHIFT..=MAX_PITCH_SHIFT)
+                            .step_by(0.5)
+                            .text("Pitch")
+                            .suffix(" st"),
+                    )
+                    .changed();
+                if ui.button("Reset").clicked() {
+                    *speed = SpeedSettings::default();
+                    speed_changed = true;
+                }
+            });
+            if speed_changed {
+                if let Some(player) = &self.player {
+                    player.set_speed(&self.settings.speed);
+                }
+                self.save_settings();
+            }
+            
+
+            let (mut set_loop_a, mut set_loop_b, mut clear_loop) = (false, false, false);
+            let mut loop_repeats_changed = false;
+            let mut seek_to = None;
+
+            if let Some(audio_file) = self.current_track() {
+                ui.label(format!("Now: {}", audio_file.title));
+                if let Some(player) = &self.player {
+                    let current_pos = player.get_position();
+                    let total_duration = audio_file.duration.unwrap_or(Duration::ZERO);
+                    let approx = if audio_file.is_duration_estimated() { "~" } else { "" };
+
+                    let mut progress = if total_duration.as_secs() > 0 {
+                        current_pos.as_secs_f64() / total_duration.as_secs_f64()
+                    } else {
+                        0.0
+                    };
+                    progress = progress.min(1.0);
+                    let waveform = self
+                        .waveform
+                        .as_ref()
+                        .filter(|(path, _)| *path == audio_file.path)
+                        .map(|(_, waveform)| waveform);
+                    let theme = if self.is_dark_theme { MOCHA } else { LATTE };
+                    if let Some(fraction) = draw_seekbar(ui, waveform, progress as f32, &theme, self.loop_a, self.loop_b, total_duration) {
+                        seek_to = Some(total_duration.mul_f32(fraction));
+                    }
+                    
+                    // Show time below slider: "1:23 / 3:45"
+                    ui.label(format!(
+                        "{} / {}{}", 
+                        format_duration(current_pos),
+                        approx,
+                        format_duration(total_duration)
+                    ));
+
+                    let key_for = |action: Action| ctx.format_shortcut(&self.settings.keybindings.get(action).shortcut());
+                    ui.horizontal(|ui| {
+                        set_loop_a |= ui
+                            .selectable_label(self.loop_a.is_some(), "A")
+                            .on_hover_text(format!("Set loop start here ({})", key_for(Action::SetLoopA)))
+                            .clicked();
+                        set_loop_b |= ui
+                            .selectable_label(self.loop_b.is_some(), "B")
+                            .on_hover_text(format!("Set loop end here ({})", key_for(Action::SetLoopB)))
+                            .clicked();
+                        let has_markers = self.loop_a.is_some() || self.loop_b.is_some();
+                        clear_loop |= ui
+                            .add_enabled(has_markers, egui::Button::new("✖ Loop"))
+                            .on_hover_text(format!("Clear loop ({})", key_for(Action::ClearLoop)))
+                            .clicked();
+                        ui.label("Repeat");
+                        loop_repeats_changed = ui
+                            .add(
+                                egui::DragValue::new(&mut self.loop_repeats)
+                                    .range(0..=99)
+                                    .custom_formatter(|n, _| if n == 0.0 { "∞".to_string() } else { format!("{}×", n) }),
+                            )
+                            .changed();
+                        if self.loop_a.is_some() && self.loop_b.is_some() && self.loop_repeats > 0 {
+                            ui.label(format!("{}/{}", player.loop_passes(), self.loop_repeats));
+                        }
+                    });
+                }
+            }
+
+            if let Some(position) = seek_to
+                && let Some(player) = &mut self.player
+                && let Err(e) = player.seek(position)
+            {
+                self.error_message = Some(format!("Can't seek: {}", e));
+            }
+
+            if set_loop_a {
+                self.set_loop_a();
+            }
+            if set_loop_b {
+                self.set_loop_b();
+            }
+            if clear_loop {
+                self.loop_a = None;
+                self.loop_b = None;
+            }
+            if set_loop_a || set_loop_b || clear_loop || loop_repeats_changed {
+                self.apply_loop();
+            }
+
+            if self.settings.visualizer.enabled && self.player.is_some() {
+                draw_visualizer(ui, &self.analyzer);
+            }
+
+            ui.separator();
+
+            // Track list
+            let mut play_top_hit = false;
+            ui.horizontal(|ui| {
+                ui.menu_button("Columns", |ui| {
+                    for column in TrackColumn::ALL {
+                        let mut visible = self.visible_columns.contains(&column);
+                        if ui.checkbox(&mut visible, column.label()).changed() {
+                            if visible {
+                                self.visible_columns.push(column);
+                            } else {
+                                self.visible_columns.retain(|c| *c != column);
+                            }
+                        }
+                    }
+                });
+                if ui.button("ℹ Properties").clicked() {
+                    self.show_properties = true;
+                }
+                let search = ui.add(
+                    egui::TextEdit::singleline(&mut self.search_query)
+                        .hint_text("🔍 Title, artist, album or path"),
+                );
+                if self.focus_search {
+                    search.request_focus();
+                    self.focus_search = false;
+                }
+                play_top_hit = search.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                ui.selectable_value(&mut self.search_library, false, "Playlist");
+                ui.selectable_value(&mut self.search_library, true, "Library");
+            });
+
+            // Matches best first while searching, everything in order otherwise
+            let searching = !self.search_query.trim().is_empty();
+            let in_library = searching && self.search_library;
+            let files = if in_library {
+                &self.library_files[..]
+            } else {
+                self.playlist.as_ref().map(|p| p.all_files()).unwrap_or(&[])
+            };
+            let rows: Vec<usize> = if searching {
+                crate::search::search(files, &self.search_query)
+            } else {
+                (0..files.len()).collect()
+            };
+            let current_path = self.current_track().map(|file| &file.path);
+            // Headers between albums, unless it's all one album anyway
+            let show_album_headers = !searching && files.iter().any(|file| album_of(file) != album_of(&files[0]));
+            let mut wanted_artwork = Vec::new();
+
+            let mut clicked_track = None;
+            let mut double_clicked_track = play_top_hit.then(|| rows.first().copied()).flatten();
+            let mut sort_clicked = None;
+            // Track to queue, and whether it goes to the front
+            let mut queue_clicked = None;
+            let mut rated = None;
+            let track_list = egui::ScrollArea::vertical().show(ui, |ui| {
+                egui::Grid::new("track_list").striped(true).show(ui, |ui| {
+                    let sort = &self.settings.track_sort;
+                    if sort_header(ui, "Title", SortColumn::Title, sort) {
+                        sort_clicked = Some(SortColumn::Title);
+                    }
+                    if sort_header(ui, "Duration", SortColumn::Duration, sort) {
+                        sort_clicked = Some(SortColumn::Duration);
+                    }
+                    for column in &self.visible_columns {
+                        match column.sort_column() {
+                            Some(sort_column) => {
+                                if sort_header(ui, column.label(), sort_column, sort) {
+                                    sort_clicked = Some(sort_column);
+                                }
+                            }
+                            None => {
+                                ui.strong(column.label());
+                            }
+                        }
+                    }
+                    ui.end_row();
+
+                    let mut previous_album = None;
+                    for &index in &rows {
+                        let audio_file = &files[index];
+                        if show_album_headers && previous_album != Some(album_of(audio_file)) {
+                            let artwork = self.artwork.get(&audio_file.path);
+                            if artwork.is_none() {
+                                wanted_artwork.push(audio_file.path.clone());
+                            }
+                            album_header(ui, audio_file, artwork.and_then(Option::as_ref));
+                            ui.end_row();
+                            previous_album = Some(album_of(audio_file));
+                        }
+                        let title = if current_path == Some(&audio_file.path) {
+                            format!("▶ {}", audio_file.title)
+                        } else {
+                            audio_file.title.clone()
+                        };
+                        let selected = !in_library && self.selected_track == Some(index);
+                        let response = ui.selectable_label(selected, title);
+                        if response.clicked() {
+                            clicked_track = Some(index);
+                        }
+                        if response.double_clicked() {
+                            double_clicked_track = Some(index);
+                        }
+                        response.context_menu(|ui| {
+                            if ui.button("Play next").clicked() {
+                                queue_clicked = Some((audio_file.clone(), true));
+                                ui.close();
+                            }
+                            if ui.button("Add to queue").clicked() {
+                                queue_clicked = Some((audio_file.clone(), false));
+                                ui.close();
+                            }
+                        });
+
+                        ui.label(format_track_duration(audio_file));
+                        for column in &self.visible_columns {
+                            if *column == TrackColumn::Rating {
+                                if let Some(rating) = star_rating(ui, audio_file.stats.rating)
+                                    && let Some(id) = &audio_file.id
+                                {
+                                    rated = Some((id.clone(), rating));
+                                }
+                            } else {
+                                ui.label(column.value(audio_file));
+                            }
+                        }
+                        ui.end_row();
+                    }
+                });
+            });
+            // Everything below the list's top takes drops, however short the list
+            let drop_area = egui::Rect::from_min_max(track_list.inner_rect.min, ui.max_rect().max);
+            self.drop_targets.push((drop_area, DropTarget::TrackList));
+
+            // Process clicks outside the list to avoid borrowing issues
+            for track in wanted_artwork {
+                self.artwork_loader.queue(&track);
+            }
+            if let Some((id, rating)) = rated {
+                self.library.set_rating(&id, rating);
+                self.save_library();
+                self.refresh_stats(&id);
+            }
+            if let Some((track, next)) = queue_clicked {
+                if next {
+                    self.queue.play_next(track);
+                } else {
+                    self.queue.add(track);
+                }
+                self.save_queue();
+            }
+            if let Some(column) = sort_clicked {
+                self.change_sort(column, ui.input(|i| i.modifiers.shift));
+            }
+            if let Some(index) = clicked_track
+                && !in_library
+            {
+                self.selected_track = Some(index);
+            }
+            if let Some(index) = double_clicked_track {
+                if in_library {
+                    self.play_library_track(index);
+                } else {
+                    self.selected_track = Some(index);
+                    self.play_track(index);
+                }
+            }
+        });
+        self.handle_dropped_files(ctx);
+        ctx.request_repaint();
+    }
+}
+
+impl AudioPlayerApp {
+    fn load_files(&mut self) {
+        let path = PathBuf::from(&self.folder_path);
+        match crate::audio::find_audio_files(&path) {
+            Ok(mut files) => {
+                self.library.apply(&mut files);
+                sort_for_showing(&mut files, &self.settings.track_sort);
+                self.selected_track = None;
+                self.library_index = LibraryIndex::build(&files, &path);
+                self.browse_selection = None;
+                self.library_files = files.clone();
+                self.playlist = Some(crate::playlist::Playlist::new(files));
+                self.queue_loudness_analysis();
+                self.queue_duration_scans();
+            }
+            Err(e) => {
+                self.error_message = Some(format!("Can't open {}: {}", path.display(), e));
+            }
+        }
+    }
+
+    fn toggle_play_pause(&mut self) {
+        // Nothing loaded, e.g. after a track failed to play: start afresh
+        if self.player.as_ref().is_none_or(|player| player.is_empty()) {
+            self.play_current();
+        } else if let Some(player) = &mut self.player {
+            if self.is_playing{
+                player.pause();
+                self.is_playing = false;
+            } else {
+                player.resume();
+                self.is_playing = true;
+            }
+        }
+    }
+
+    // Runs whatever the keys pressed this frame are bound to. Runs before any
+    // widget is drawn, so a focused button doesn't also get the key.
+    fn handle_shortcuts(&mut self, ctx: &egui::Context) {
+        // Keys belong to the text field being typed in, or to the binding being changed
+        if ctx.wants_keyboard_input() || self.capturing_binding.is_some() {
+            return;
+        }
+        let shortcuts = self.settings.keybindings.shortcuts();
+        let pressed: Vec<Action> = ctx.input_mut(|i| {
+            shortcuts
+                .iter()
+                .filter(|(_, shortcut)| i.consume_shortcut(shortcut))
+                .map(|(action, _)| *action)
+                .collect()
+        });
+        for action in pressed {
+            self.run_action(action);
+        }
+    }
+
+    fn run_action(&mut self, action: Action) {
+        match action {
+            Action::PlayPause => self.toggle_play_pause(),
+            Action::SeekForward => self.seek_by(SEEK_STEP.as_secs_f64()),
+            Action::SeekBackward => self.seek_by(-SEEK_STEP.as_secs_f64()),
+            Action::VolumeUp => self.change_volume(VOLUME_STEP),
+            Action::VolumeDown => self.change_volume(-VOLUME_STEP),
+            Action::Next => self.play_next(),
+            Action::Previous => self.play_previous(),
+            Action::Search => self.focus_search = true,
+            Action::RemoveTrack => self.remove_selected_track(),
+            Action::SetLoopA => {
+                self.set_loop_a();
+                self.apply_loop();
+            }
+            Action::SetLoopB => {
+                self.set_loop_b();
+                self.apply_loop();
+            }
+            Action::ClearLoop => {
+                self.loop_a = None;
+                self.loop_b = None;
+                self.apply_loop();
+            }
+        }
+    }
+
+    // Moves playback by `seconds`, staying within the track
+    fn seek_by(&mut self, seconds: f64) {
+        let total = self.current_track().and_then(|file| file.duration);
+        let Some(player) = &mut self.player else { return };
+        let mut target = (player.get_position().as_secs_f64() + seconds).max(0.0);
+        if let Some(total) = total {
+            target = target.min(total.as_secs_f64());
+        }
+        if let Err(e) = player.seek(Duration::from_secs_f64(target)) {
+            self.error_message = Some(format!("Can't seek: {}", e));
+        }
+    }
+
+    fn change_volume(&mut self, delta: f32) {
+        self.volume = (self.volume + delta).clamp(0.0, 1.0);
+        if let Some(player) = &self.player {
+            player.set_volume(self.volume);
+        }
+    }
+
+    // Takes the selected track out of the list; the file itself stays put
+    fn remove_selected_track(&mut self) {
+        let Some(index) = self.selected_track else { return };
+        let Some(playlist) = &mut self.playlist else { return };
+        // While a queued track plays, the playlist's current track isn't playing
+        let was_current = self.queued_track.is_none() && playlist.current_index() == Some(index);
+        if playlist.remove(index).is_none() {
+            return;
+        }
+        // Select the track that moved up, so Delete can be pressed again
+        self.selected_track = playlist.len().checked_sub(1).map(|last| index.min(last));
+
+        if was_current {
+            self.finish_history(false);
+            self.uncounted_play = None;
+            if let Some(player) = &mut self.player {
+                player.stop();
+            }
+            self.is_playing = false;
+        }
+    }
+
+    fn set_loop_a(&mut self) {
+        let Some(player) = &self.player else { return };
+        let position = player.get_position();
+        self.loop_a = Some(position);
+        if self.loop_b.is_some_and(|b| b <= position) {
+            self.loop_b = None;
+        }
+    }
+
+    fn set_loop_b(&mut self) {
+        let Some(player) = &self.player else { return };
+        let position = player.get_position();
+        let a = *self.loop_a.get_or_insert(Duration::ZERO);
+        if position > a {
+            self.loop_b = Some(position);
+        }
+    }
+
+    // Hands the markers to the player once both are set
+    fn apply_loop(&mut self) {
+        let Some(player) = &self.player else { return };
+        let points = match (self.loop_a, self.loop_b) {
+            (Some(a), Some(b)) => Some(AbLoop {
+                a,
+                b,
+                repeats: (self.loop_repeats > 0).then_some(self.loop_repeats),
+            }),
+            _ => None,
+        };
+        player.set_loop(points);
+    }
+
+    // Moves on to the next track once the current one has played out
+    fn advance_if_finished(&mut self) {
+        if self.is_playing
+            && let Some(player) = &self.player
+            && player.track_finished()
+        {
+            self.record_finished_track();
+            self.finish_history(true);
+            // Playing to the end always counts, however short the track
+            if let Some(id) = self.uncounted_play.take() {
+                self.record_play(&id);
+            }
+
+            let is_last_track = self.queue.is_empty() && self.playlist.as_ref().is_none_or(|p| p.is_last());
+            let sleep_now = match self.sleep_timer.as_ref().map(|timer| timer.mode) {
+                Some(SleepMode::EndOfTrack) => true,
+                Some(SleepMode::EndOfPlaylist) => is_last_track,
+                _ => false,
+            };
+            if sleep_now {
+                self.go_to_sleep();
+            } else {
+                self.play_next();
+            }
+        }
+    }
+
+    fn start_sleep_timer(&mut self, mode: SleepMode) {
+        let remaining = self.remaining_playback(mode == SleepMode::EndOfPlaylist);
+        self.sleep_timer = Some(SleepTimer::start(mode, remaining));
+    }
+
+    fn cancel_sleep_timer(&mut self) {
+        self.sleep_timer = None;
+        if let Some(player) = &self.player {
+            player.set_sleep_deadline(None);
+        }
+    }
+
+    fn go_to_sleep(&mut self) {
+        self.sleep_timer = None;
+        self.finish_history(false);
+        self.uncounted_play = None;
+        if let Some(player) = &mut self.player {
+            player.stop();
+            player.set_sleep_deadline(None);
+        }
+        self.is_playing = false;
+    }
+
+    // The player fades out towards the deadline on its own, so this only has
+    // to keep the deadline current and stop playback once it's passed
+    fn update_sleep_timer(&mut self) {
+        let Some(mode) = self.sleep_timer.as_ref().map(|timer| timer.mode) else { return };
+        let remaining = match mode {
+            SleepMode::After(_) => None,
+            SleepMode::EndOfTrack => self.remaining_playback(false),
+            SleepMode::EndOfPlaylist => self.remaining_playback(true),
+        };
+        let Some(timer) = &mut self.sleep_timer else { return };
+        if !matches!(mode, SleepMode::After(_)) {
+            timer.update_remaining(remaining);
+        }
+
+        if timer.is_expired() {
+            self.go_to_sleep();
+        } else if let Some(player) = &self.player {
+            player.set_sleep_deadline(timer.deadline());
+        }
+    }
+
+    // Listening time left in the current track, or up to the end of the
+    // playlist. Unknown while nothing plays or any track's length is unknown.
+    fn remaining_playback(&self, to_playlist_end: bool) -> Option<Duration> {
+        let player = self.player.as_ref()?;
+        if !self.is_playing {
+            return None;
+        }
+
+        let mut remaining = self.current_track()?.duration?.saturating_sub(player.get_position());
+        if to_playlist_end {
+            let upcoming = self.playlist.as_ref().map(|playlist| playlist.upcoming()).unwrap_or_default();
+            for file in self.queue.tracks().iter().chain(upcoming) {
+                remaining += file.duration?;
+            }
+        }
+        Some(remaining.div_f32(self.settings.speed.speed))
+    }
+
+    // Feeds what was played since the last frame to the spectrum and level meter
+    fn update_visualizer(&mut self, ctx: &egui::Context) {
+        let Some(player) = &mut self.player else { return };
+        if !self.settings.visualizer.enabled {
+            return;
+        }
+        self.tap_samples.clear();
+        let sample_rate = player.drain_tap(&mut self.tap_samples);
+        let elapsed = Duration::from_secs_f32(ctx.input(|i| i.stable_dt).min(1.0));
+        self.analyzer.update(&self.tap_samples, sample_rate, elapsed, &self.settings.visualizer);
+    }
+
+    // The track that's playing: one off the queue, or else the playlist's current track
+    fn current_track(&self) -> Option<&crate::audio::AudioFile> {
+        self.queued_track.as_ref().or_else(|| self.playlist.as_ref()?.current())
+    }
+
+    fn current_track_mut(&mut self) -> Option<&mut crate::audio::AudioFile> {
+        match &mut self.queued_track {
+            Some(track) => Some(track),
+            None => self.playlist.as_mut()?.current_mut(),
+        }
+    }
+
+    // Plays the current track, opening the output on first use
+    fn play_current(&mut self) {
+        let shuffled = self.playlist.as_ref().is_some_and(|p| p.is_shuffled());
+        let source = match (&self.queued_track, &self.selected_playlist) {
+            (Some(_), _) => PlaySource::Queue,
+            (None, Some(name)) => PlaySource::Playlist(name.clone()),
+            (None, None) => PlaySource::Library,
+        };
+        let Some(audio_file) = self.current_track() else { return };
+        let gain = self.settings.replay_gain.factor(&audio_file.replay_gain, shuffled);
+        let path = audio_file.path.clone();
+        let title = audio_file.title.clone();
+
+        if self.player.is_none() {
+            match crate::player::Player::new(&self.settings.output) {
+                Ok(player) => {
+                    player.set_volume(self.volume);
+                    player.set_equalizer(&self.settings.equalizer);
+                    player.set_stereo(&self.settings.stereo);
+                    player.set_speed(&self.settings.speed);
+                    player.set_fade_duration(self.settings.fade.duration());
+                    player.set_tap_enabled(self.settings.visualizer.enabled);
+                    self.player = Some(player);
+                }
+                Err(e) => {
+                    self.error_message = Some(format!("Can't open audio output: {}", e));
+                    self.is_playing = false;
+                    return;
+                }
+            }
+        }
+
+        // Loop markers belong to the track they were set in
+        self.loop_a = None;
+        self.loop_b = None;
+
+        if self.waveform.as_ref().is_none_or(|(waveform_path, _)| *waveform_path != path) {
+            self.waveform = None;
+            self.waveform_loader.queue(&path);
+        }
+
+        // Whatever was playing before stops here, and is skipped if it
+        // hadn't played long enough to count
+        self.finish_history(false);
+        if let Some(id) = self.uncounted_play.take() {
+            self.library.record_skip(&id);
+            self.save_library();
+            self.refresh_stats(&id);
+        }
+        let id = self.current_track().and_then(|file| file.id.clone());
+
+        if let Some(player) = &mut self.player {
+            match player.play(&path, gain) {
+                Ok(()) => {
+                    self.is_playing = true;
+                    self.history.start(&path, &title, source);
+                    self.save_history();
+                    self.uncounted_play = id;
+                }
+                Err(e) => {
+                    // Stop here rather than letting auto-advance spin through the playlist
+                    self.error_message = Some(format!("Can't play {}: {}", title, e));
+                    self.is_playing = false;
+                }
+            }
+        }
+    }
+
+    // Loads the library tracks under a browser node into the playlist
+    fn browse(&mut self, node: BrowseNode) {
+        let mut files: Vec<_> = self.library_files.iter().filter(|file| node.contains(file)).cloned().collect();
+        self.library.apply(&mut files);
+        sort_for_showing(&mut files, &self.settings.track_sort);
+
+        let shuffle = self.playlist.as_ref().is_some_and(|p| p.is_shuffled());
+        let mut playlist = crate::playlist::Playlist::new(files);
+        playlist.set_shuffle(shuffle);
+        self.playlist = Some(playlist);
+        self.selected_playlist = None;
+        self.selected_track = None;
+        self.browse_selection = Some(node);
+    }
+
+    fn collect_artwork(&mut self, ctx: &egui::Context) {
+        for (track, image) in self.artwork_loader.poll() {
+            let texture = image.map(|image| ctx.load_texture(track.to_string_lossy(), image, egui::TextureOptions::LINEAR));
+            self.artwork.insert(track, texture);
+        }
+    }
+
+    // Makes the whole library folder the playlist and plays one of its tracks
+    fn play_library_track(&mut self, index: usize) {
+        let Some(path) = self.library_files.get(index).map(|file| file.path.clone()) else { return };
+        let mut files = self.library_files.clone();
+        self.library.apply(&mut files);
+        sort_for_showing(&mut files, &self.settings.track_sort);
+        let index = files.iter().position(|file| file.path == path);
+
+        let shuffle = self.playlist.as_ref().is_some_and(|p| p.is_shuffled());
+        let mut playlist = crate::playlist::Playlist::new(files);
+        playlist.set_shuffle(shuffle);
+        self.playlist = Some(playlist);
+        self.selected_playlist = None;
+        self.selected_track = index;
+        if let Some(index) = index {
+            self.play_track(index);
+        }
+    }
+
+    // A click sorts by one column, ascending, then descending, then back to
+    // the default order. Shift+click adds the column as the next key, or flips it.
+    fn change_sort(&mut self, column: SortColumn, add: bool) {
+        let keys = &mut self.settings.track_sort;
+        match keys.iter().position(|key| key.column == column) {
+            Some(i) if add => keys[i].descending = !keys[i].descending,
+            Some(_) if keys.len() == 1 && keys[0].descending => keys.clear(),
+            Some(_) if keys.len() == 1 => keys[0].descending = true,
+            _ if add => keys.push(SortKey { column, descending: false }),
+            _ => *keys = vec![SortKey { column, descending: false }],
+        }
+
+        if let Some(playlist) = &mut self.playlist {
+            let selected = self.selected_track.and_then(|i| playlist.all_files().get(i)).map(|file| file.path.clone());
+            playlist.sort(&self.settings.track_sort);
+            self.selected_track = selected.and_then(|path| playlist.all_files().iter().position(|file| file.path == path));
+        }
+        sort_files(&mut self.library_files, &self.settings.track_sort);
+        self.save_settings();
+    }
+
+    fn play_track(&mut self, index: usize) {
+        if self.playlist.as_mut().and_then(|p| p.select(index)).is_some() {
+            self.queued_track = None;
+            self.play_current();
+        }
+    }
+
+    // Plays a queued track straight away, taking it off the queue
+    fn play_from_queue(&mut self, index: usize) {
+        if let Some(track) = self.queue.remove(index) {
+            self.queued_track = Some(track);
+            self.save_queue();
+            self.play_current();
+        }
+    }
+
+    // Plays a track from the history again, leaving the playlist where it was
+    fn replay(&mut self, path: PathBuf) {
+        let known = self
+            .playlist
+            .iter()
+            .flat_map(|playlist| playlist.all_files())
+            .chain(&self.library_files)
+            .find(|file| file.path == path)
+            .cloned();
+        let track = match known {
+            Some(track) => track,
+            None if path.is_file() => crate::audio::AudioFile::new(path),
+            None => {
+                self.error_message = Some(format!("{} is no longer there", path.display()));
+                return;
+            }
+        };
+        self.queued_track = Some(track);
+        self.play_current();
+    }
+
+    // Counts the current track as played once enough of it has been heard
+    fn count_play(&mut self) {
+        let Some(player) = &self.player else { return };
+        if self.uncounted_play.is_some()
+            && crate::library::counts_as_played(player.get_position(), self.current_track().and_then(|file| file.duration))
+            && let Some(id) = self.uncounted_play.take()
+        {
+            self.record_play(&id);
+        }
+    }
+
+    fn record_play(&mut self, id: &TrackId) {
+        self.library.record_play(id);
+        self.save_library();
+        self.refresh_stats(id);
+    }
+
+    // Copies a track's stats from the library to every list it's shown in
+    fn refresh_stats(&mut self, id: &TrackId) {
+        let stats = self.library.stats_by_id(id);
+        let playlist = self.playlist.iter_mut().flat_map(|playlist| playlist.all_files_mut());
+        let files = playlist
+            .chain(self.library_files.iter_mut())
+            .chain(self.queue.tracks_mut())
+            .chain(self.queued_track.iter_mut());
+        for file in files.filter(|file| file.id.as_ref() == Some(id)) {
+            file.stats = stats.clone();
+        }
+    }
+
+    fn save_library(&mut self) {
+        if let Err(e) = self.library.save() {
+            self.error_message = Some(format!("Can't save the library: {}", e));
+        }
+    }
+
+    // Closes the history entry of the track that was playing
+    fn finish_history(&mut self, finished: bool) {
+        let Some(player) = &self.player else { return };
+        if self.history.finish(player.get_position(), finished) {
+            self.save_history();
+        }
+    }
+
+    fn save_history(&mut self) {
+        if let Err(e) = self.history.save() {
+            self.error_message = Some(format!("Can't save the history: {}", e));
+        }
+    }
+
+    fn save_queue(&mut self) {
+        if let Err(e) = self.queue.save() {
+            self.error_message = Some(format!("Can't save the play queue: {}", e));
+        }
+    }
+
+    fn save_settings(&mut self) {
+        if let Err(e) = self.settings.save() {
+            self.error_message = Some(format!("Can't save settings: {}", e));
+        }
+    }
+
+    // The queue goes first; once it's empty the playlist carries on from where it was
+    pub fn play_next(&mut self) {
+        match self.queue.advance(self.playlist.as_mut()) {
+            Advance::Queued(track) => {
+                self.queued_track = Some(*track);
+                self.save_queue();
+            }
+            Advance::Playlist => self.queued_track = None,
+            Advance::End => {
+                // Reached end
+                self.queued_track = None;
+                if let Some(player) = &mut self.player {
+                    player.stop();
+                }
+                self.is_playing = false;
+                return;
+            }
+        }
+        if self.player.is_some() {
+            self.play_current();
+        }
+    }
+
+    // Steps back through what was played: from a queued track to the
+    // playlist track it interrupted, then through the playlist's history
+    pub fn play_previous(&mut self) {
+        if self.queued_track.take().is_some()
+            && self.playlist.as_ref().is_some_and(|p| p.current().is_some())
+        {
+            if self.player.is_some() {
+                self.play_current();
+            }
+            return;
+        }
+
+        if let Some(playlist) = &mut self.playlist
+            && playlist.previous().is_some()
+            && self.player.is_some()
+        {
+            self.play_current();
+        }
+    }
+
+    // Re-evaluates the current track's gain after a setting it depends on changed
+    fn apply_replay_gain(&self) {
+        let shuffled = self.playlist.as_ref().is_some_and(|p| p.is_shuffled());
+        if let Some(player) = &self.player
+            && let Some(audio_file) = self.current_track()
+        {
+            player.set_gain(self.settings.replay_gain.factor(&audio_file.replay_gain, shuffled));
+        }
+    }
+
+    // A track that played to the end tells us its real length, which beats
+    // any estimate made while scanning.
+    fn record_finished_track(&mut self) {
+        let Some(player) = &self.player else { return };
+        let played = player.get_position();
+
+        if played >= MIN_PLAYED_DURATION
+            && let Some(audio_file) = self.current_track_mut()
+            && audio_file.is_duration_estimated()
+        {
+            audio_file.set_duration(played, crate::audio::DurationAccuracy::Exact);
+            let path = audio_file.path.clone();
+            self.library.record_played_duration(&path, played);
+            self.save_library();
+        }
+    }
+
+    // Hands every track that has no gain to normalize with over to the analyzer
+    fn queue_loudness_analysis(&mut self) {
+        if !self.settings.loudness.analyze {
+            return;
+        }
+        if let Some(playlist) = &self.playlist {
+            for audio_file in playlist.all_files() {
+                if audio_file.replay_gain.track_gain.is_none() && !self.loudness_failures.contains_key(&audio_file.path) {
+                    self.loudness_analyzer.queue(&audio_file.path);
+                }
+            }
+        }
+    }
+
+    // Hands every track whose length is only a guess over to the scanner
+    fn queue_duration_scans(&mut self) {
+        if let Some(playlist) = &self.playlist {
+            for audio_file in playlist.all_files() {
+                if self.library.needs_duration_scan(audio_file) {
+                    self.duration_scanner.queue(&audio_file.path);
+                }
+            }
+        }
+    }
+
+    fn collect_duration_scans(&mut self) {
+        let results = self.duration_scanner.poll();
+        if results.is_empty() {
+            return;
+        }
+
+        for (path, duration) in results {
+            if let Some(duration) = duration {
+                self.library.record_scanned_duration(&path, duration);
+            }
+        }
+
+        if let Some(playlist) = &mut self.playlist {
+            self.library.apply(playlist.all_files_mut());
+        }
+        self.library.apply(&mut self.library_files);
+        self.save_library();
+    }
+
+    // Keeps the waveform that finished loading if it's still for the current track
+    fn collect_waveforms(&mut self) {
+        let current = self.current_track().map(|file| file.path.clone());
+        for (path, result) in self.waveform_loader.poll() {
+            match result {
+                _ if current.as_ref() != Some(&path) => {}
+                Ok(waveform) => self.waveform = Some((path, waveform)),
+                Err(e) => self.error_message = Some(format!("Can't draw the waveform of {}: {}", path.display(), e)),
+            }
+        }
+    }
+
+    fn collect_loudness_results(&mut self) {
+        let results = self.loudness_analyzer.poll();
+        if results.is_empty() {
+            return;
+        }
+
+        for (path, result) in results {
+            match result {
+                Ok(loudness) => {
+                    self.library.record_loudness(&path, loudness);
+                    if self.settings.loudness.write_tags {
+                        let replay_gain = crate::replaygain::ReplayGain {
+                            track_gain: Some(loudness.replay_gain() as f32),
+                            track_peak: Some(loudness.true_peak as f32),
+                            ..Default::default()
+                        };
+                        if let Err(e) = crate::replaygain::write_track_tags(&path, &replay_gain) {
+                            self.loudness_failures.insert(path, format!("can't write ReplayGain tags: {}", e));
+                        }
+                    }
+                }
+                Err(e) => {
+                    self.loudness_failures.insert(path, e);
+                }
+            }
+        }
+
+        if let Some(playlist) = &mut self.playlist {
+            self.library.apply(playlist.all_files_mut());
+        }
+        self.apply_replay_gain();
+        self.save_library();
+    }
+
+    fn refresh_output_devices(&mut self) {
+        match crate::output::output_devices() {
+            Ok(devices) => self.output_devices = devices,
+            Err(e) => self.error_message = Some(format!("Can't list output devices: {}", e)),
+        }
+    }
+
+    // Shows where files being dragged over the window would go, and puts
+    // them there once they're dropped
+    fn handle_dropped_files(&mut self, ctx: &egui::Context) {
+        let (hovering, dropped, pointer) =
+            ctx.input(|i| (!i.raw.hovered_files.is_empty(), i.raw.dropped_files.clone(), i.pointer.latest_pos()));
+        // Some platforms don't report the pointer during a drag, which makes
+        // every drop a drop on empty space
+        let target = pointer.and_then(|pos| self.drop_targets.iter().find(|(rect, _)| rect.contains(pos)));
+
+        if hovering {
+            let painter = ctx.layer_painter(egui::LayerId::new(egui::Order::Foreground, egui::Id::new("drop_hint")));
+            let (rect, hint) = match target {
+                Some((rect, target)) => (*rect, target.hint()),
+                None => (ctx.screen_rect(), "Play".to_string()),
+            };
+            let color = ctx.style().visuals.selection.stroke.color;
+            painter.rect_stroke(rect, 4.0, egui::Stroke::new(2.0, color), egui::StrokeKind::Inside);
+            painter.text(rect.center(), egui::Align2::CENTER_CENTER, hint, egui::FontId::proportional(20.0), color);
+        }
+
+        if dropped.is_empty() {
+            return;
+        }
+        let target = target.map(|(_, target)| target.clone());
+
+        // Folders bring in everything under them
+        let mut files = Vec::new();
+        let mut problems = Vec::new();
+        for path in dropped.into_iter().filter_map(|file| file.path) {
+            match crate::audio::find_audio_files(&path) {
+                Ok(found) => files.extend(found),
+                Err(e) => problems.push(format!("Can't open {}: {}", path.display(), e)),
+            }
+        }
+        if files.is_empty() {
+            problems.insert(0, "No audio files in what was dropped".to_string());
+        }
+        if !problems.is_empty() {
+            self.error_message = Some(problems.join("; "));
+        }
+        if files.is_empty() {
+            return;
+        }
+        self.library.apply(&mut files);
+        sort_for_showing(&mut files, &self.settings.track_sort);
+
+        match target {
+            Some(DropTarget::TrackList) => {
+                match &mut self.playlist {
+                    Some(playlist) => playlist.append(files),
+                    None => self.playlist = Some(crate::playlist::Playlist::new(files)),
+                }
+                self.queue_loudness_analysis();
+                self.queue_duration_scans();
+            }
+            Some(DropTarget::Playlist(name)) => {
+                match self.playlist_manager.add_to_playlist(&name, &files) {
+                    Ok(skipped) if skipped.len() == 1 => {
+                        let file = skipped[0].file_name().unwrap_or_default().to_string_lossy();
+                        self.error_message = Some(format!("{} is already in playlist {}", file, name));
+                    }
+                    Ok(skipped) if !skipped.is_empty() => {
+                        self.error_message = Some(format!("{} tracks were already in playlist {}", skipped.len(), name));
+                    }
+                    Ok(_) => {}
+                    Err(e) => self.error_message = Some(format!("Can't add to playlist {}: {}", name, e)),
+                }
+                if self.selected_playlist.as_ref() == Some(&name) {
+                    self.load_playlist_songs(&name);
+                }
+            }
+            None => {
+                let shuffle = self.playlist.as_ref().is_some_and(|p| p.is_shuffled());
+                let mut playlist = crate::playlist::Playlist::new(files);
+                playlist.set_shuffle(shuffle);
+                self.playlist = Some(playlist);
+                self.selected_playlist = None;
+                self.browse_selection = None;
+                self.selected_track = Some(0);
+                self.queue_loudness_analysis();
+                self.queue_duration_scans();
+                self.play_track(0);
+            }
+        }
+    }
+
+    fn refresh_playlists(&mut self) {
+        if let Ok(names) = self.playlist_manager.scan_playlists() {
+            self.playlist_names = names;
+        }
+        if let Ok(names) = self.playlist_manager.scan_smart_playlists() {
+            self.smart_playlist_names = names;
+        }
+    }
+
+    // Fills the playlist with the library tracks a smart playlist's rules pick out
+    fn load_smart_playlist_songs(&mut self, playlist_name: &str) {
+        match self.playlist_manager.load_smart_playlist(playlist_name) {
+            Ok(smart_playlist) => {
+                let songs = smart_playlist.evaluate(&self.library_files);
+                self.selected_track = None;
+                self.browse_selection = None;
+                self.playlist = Some(crate::playlist::Playlist::new(songs));
+                self.queue_loudness_analysis();
+                self.queue_duration_scans();
+            }
+            Err(e) => self.error_message = Some(format!("Can't open smart playlist {}: {}", playlist_name, e)),
+        }
+    }
+
+    fn edit_smart_playlist(&mut self, name: &str) {
+        match self.playlist_manager.load_smart_playlist(name) {
+            Ok(playlist) => {
+                self.smart_editor = Some(SmartPlaylistEditor {
+                    name: name.to_string(),
+                    original_name: Some(name.to_string()),
+                    playlist,
+                });
+            }
+            Err(e) => self.error_message = Some(format!("Can't open smart playlist {}: {}", name, e)),
+        }
+    }
+
+    fn save_smart_playlist(&mut self, editor: SmartPlaylistEditor) {
+        let name = editor.name.trim().to_string();
+        if let Err(e) = self.playlist_manager.save_smart_playlist(&name, &editor.playlist) {
+            self.error_message = Some(format!("Can't save smart playlist {}: {}", name, e));
+            return;
+        }
+        // A rename leaves the old rule file behind otherwise
+        if let Some(original) = &editor.original_name
+            && *original != name
+            && let Err(e) = self.playlist_manager.delete_smart_playlist(original)
+        {
+            self.error_message = Some(format!("Can't remove old smart playlist {}: {}", original, e));
+        }
+        self.refresh_playlists();
+
+        if editor.original_name.is_some() && self.selected_playlist == editor.original_name {
+            self.selected_playlist = Some(name.clone());
+            self.load_smart_playlist_songs(&name);
+        }
+    }
 
     // NEW: Load songs from selected playlist
     fn load_playlist_songs(&mut self, playlist_name: &str) {
-        if let Ok(songs) = self.playlist_manager.get_playlist_songs(playlist_name) {
-            self.playlist = Some(crate::playlist::Playlist::new(songs));
-        } else {
-            eprintln!("Failed to load songs from playlist: {}", playlist_name);
+        match self.playlist_manager.get_playlist_songs(playlist_name) {
+            Ok(mut songs) => {
+                self.library.apply(&mut songs);
+                sort_for_showing(&mut songs, &self.settings.track_sort);
+                self.selected_track = None;
+                self.browse_selection = None;
+                self.playlist = Some(crate::playlist::Playlist::new(songs));
+                self.queue_loudness_analysis();
+                self.queue_duration_scans();
+            }
+            Err(e) => self.error_message = Some(format!("Can't open playlist {}: {}", playlist_name, e)),
+        }
+    }
+}
+
+// Entry in the library browser that loads `node` when clicked
+fn browse_item(ui: &mut egui::Ui, node: BrowseNode, text: String, selection: Option<&BrowseNode>, chosen: &mut Option<BrowseNode>) {
+    if ui.selectable_label(selection == Some(&node), text).clicked() {
+        *chosen = Some(node);
+    }
+}
+
+fn browse_folder(ui: &mut egui::Ui, folder: &FolderNode, selection: Option<&BrowseNode>, chosen: &mut Option<BrowseNode>) {
+    let node = BrowseNode::Folder(folder.path.clone());
+    if folder.children.is_empty() {
+        browse_item(ui, node, folder.name.clone(), selection, chosen);
+        return;
+    }
+    egui::CollapsingHeader::new(&folder.name)
+        .id_salt(&folder.path)
+        .default_open(selection.is_none())
+        .show(ui, |ui| {
+            browse_item(ui, node, "All tracks here".to_string(), selection, chosen);
+            for child in &folder.children {
+                browse_folder(ui, child, selection, chosen);
+            }
+        });
+}
+
+fn album_label(album: &Album) -> String {
+    match album.year {
+        Some(year) => format!("{} ({})", album.title, year),
+        None => album.title.clone(),
+    }
+}
+
+// Cover, title and artist of an album in the browser's album grid
+fn album_tile(ui: &mut egui::Ui, album: &Album, artwork: Option<&egui::TextureHandle>, selected: bool) -> egui::Response {
+    let size = egui::vec2(ALBUM_TILE_SIZE, ALBUM_TILE_SIZE);
+    ui.allocate_ui(egui::vec2(ALBUM_TILE_SIZE, ALBUM_TILE_SIZE + 40.0), |ui| {
+        ui.vertical(|ui| {
+            let response = match artwork {
+                Some(texture) => ui.add(egui::Button::image(egui::Image::new((texture.id(), size))).selected(selected)),
+                None => ui.add_sized(size, egui::Button::new("♪").selected(selected)),
+            };
+            ui.add(egui::Label::new(egui::RichText::new(album_label(album)).strong()).truncate());
+            ui.add(egui::Label::new(album.artist.as_deref().unwrap_or("Unknown artist")).truncate());
+            response
+        })
+        .inner
+    })
+    .inner
+}
+
+// What groups a track with the others on its album
+fn album_of(file: &crate::audio::AudioFile) -> (Option<&str>, Option<&str>) {
+    (file.tags.album_artist(), file.tags.album.as_deref())
+}
+
+// Row above each album's tracks in the track list
+fn album_header(ui: &mut egui::Ui, audio_file: &crate::audio::AudioFile, artwork: Option<&egui::TextureHandle>) {
+    let tags = &audio_file.tags;
+    ui.horizontal(|ui| {
+        if let Some(texture) = artwork {
+            ui.image((texture.id(), egui::vec2(ALBUM_HEADER_ART_SIZE, ALBUM_HEADER_ART_SIZE)));
+        }
+        let mut text = tags.album.clone().unwrap_or_else(|| "Unknown album".to_string());
+        if let Some(artist) = tags.album_artist() {
+            text = format!("{} — {}", text, artist);
+        }
+        if let Some(year) = tags.year {
+            text = format!("{} ({})", text, year);
         }
+        ui.strong(text);
+    });
+}
+
+// Column header that shows its place in the sort; returns whether it was clicked
+fn sort_header(ui: &mut egui::Ui, label: &str, column: SortColumn, keys: &[SortKey]) -> bool {
+    let text = match keys.iter().position(|key| key.column == column) {
+        Some(i) => {
+            let arrow = if keys[i].descending { "⏷" } else { "⏶" };
+            // Number the keys once there's more than one
+            if keys.len() > 1 { format!("{} {}{}", label, arrow, i + 1) } else { format!("{} {}", label, arrow) }
+        }
+        None => label.to_string(),
+    };
+    ui.add(egui::Button::new(egui::RichText::new(text).strong()).frame(false))
+        .on_hover_text("Click to sort, Shift+click to sort by this next")
+        .clicked()
+}
+
+// Calendar date in UTC, e.g. "2024-03-09"
+fn format_date(time: std::time::SystemTime) -> String {
+    let days = time.duration_since(std::time::UNIX_EPOCH).map(|d| d.as_secs() / 86_400).unwrap_or(0) as i64;
+    // Days since 1970 to a civil date, after Howard Hinnant's algorithm
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+// Date and UTC time of day, as YYYY-MM-DD HH:MM
+fn format_date_time(time: std::time::SystemTime) -> String {
+    let secs = time.duration_since(std::time::UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    format!("{} {:02}:{:02}", format_date(time), secs / 3600 % 24, secs / 60 % 60)
+}
+
+// Start of the day written as YYYY-MM-DD, the reverse of format_date
+fn parse_date(text: &str) -> Option<std::time::SystemTime> {
+    let mut parts = text.trim().splitn(3, '-');
+    let year: i64 = parts.next()?.parse().ok()?;
+    let month: i64 = parts.next()?.parse().ok()?;
+    let day: i64 = parts.next()?.parse().ok()?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
     }
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month_index = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * month_index + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+    let secs = u64::try_from(days * 86_400).ok()?;
+    Some(std::time::UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+fn format_rating(rating: u8) -> String {
+    (1..=5).map(|star| if star <= rating { '★' } else { '☆' }).collect()
+}
+
+// Five clickable stars; clicking the one already set clears the rating
+fn star_rating(ui: &mut egui::Ui, rating: u8) -> Option<u8> {
+    let mut clicked = None;
+    ui.horizontal(|ui| {
+        ui.spacing_mut().item_spacing.x = 0.0;
+        for star in 1..=5 {
+            let text = if star <= rating { "★" } else { "☆" };
+            if ui.add(egui::Label::new(text).sense(egui::Sense::click())).clicked() {
+                clicked = Some(if star == rating { 0 } else { star });
+            }
+        }
+    });
+    clicked
 }
 
 fn format_duration(duration: Duration) -> String {
@@ -300,4 +2781,154 @@ fn format_duration(duration: Duration) -> String {
     let minutes = total_secs / 60;
     let seconds = total_secs % 60;
     format!("{}:{:02}", minutes, seconds)
-}
\ No newline at end of file
+}
+
+fn format_track_duration(audio_file: &crate::audio::AudioFile) -> String {
+    match audio_file.duration {
+        Some(duration) if audio_file.is_duration_estimated() => format!("~{}", format_duration(duration)),
+        Some(duration) => format_duration(duration),
+        None => "—".to_string(),
+    }
+}
+
+fn format_frequency(frequency: f32) -> String {
+    if frequency >= 1000.0 {
+        format!("{}k", frequency / 1000.0)
+    } else {
+        format!("{}", frequency)
+    }
+}
+
+// Plots the equalizer's frequency response on a log scale from 20 Hz to 20 kHz
+fn draw_eq_curve(ui: &mut egui::Ui, response: &EqResponse) {
+    let size = egui::vec2(ui.available_width().max(300.0), EQ_CURVE_HEIGHT);
+    let (rect, _) = ui.allocate_exact_size(size, egui::Sense::hover());
+    let painter = ui.painter_at(rect);
+    let visuals = ui.visuals();
+
+    painter.rect_filled(rect, 2.0, visuals.extreme_bg_color);
+    let grid = visuals.widgets.noninteractive.bg_stroke;
+    painter.hline(rect.x_range(), rect.center().y, grid);
+
+    let (low, high) = (20f32.log10(), 20000f32.log10());
+    let x_for = |frequency: f32| rect.left() + (frequency.log10() - low) / (high - low) * rect.width();
+    for frequency in GRAPHIC_FREQUENCIES {
+        painter.vline(x_for(frequency), rect.y_range(), grid);
+    }
+
+    let points = (0..=rect.width() as usize)
+        .map(|i| {
+            let x = rect.left() + i as f32;
+            let frequency = 10f32.powf(low + (x - rect.left()) / rect.width() * (high - low));
+            let db = response.at(frequency).clamp(-EQ_CURVE_RANGE, EQ_CURVE_RANGE);
+            egui::pos2(x, rect.center().y - db / EQ_CURVE_RANGE * rect.height() / 2.0)
+        })
+        .collect();
+    painter.add(egui::Shape::line(points, egui::Stroke::new(2.0, visuals.selection.bg_fill)));
+}
+
+// Draws the current track's waveform, tinted up to `progress`, with the A-B
+// loop over it. Returns where in the track (0.0 to 1.0) the user clicked or
+// let go of a drag, to seek there. The track's length is drawn flat until
+// its waveform has loaded.
+fn draw_seekbar(
+    ui: &mut egui::Ui,
+    waveform: Option<&Waveform>,
+    progress: f32,
+    theme: &Theme,
+    loop_a: Option<Duration>,
+    loop_b: Option<Duration>,
+    total: Duration,
+) -> Option<f32> {
+    let size = egui::vec2(ui.available_width(), SEEKBAR_HEIGHT);
+    let (rect, response) = ui.allocate_exact_size(size, egui::Sense::click_and_drag());
+    let fraction_at = |pos: egui::Pos2| ((pos.x - rect.left()) / rect.width()).clamp(0.0, 1.0);
+
+    // While dragging, show where playback will jump to
+    let pointer = response.interact_pointer_pos().map(fraction_at);
+    let progress = if response.dragged() { pointer.unwrap_or(progress) } else { progress };
+    let played_x = rect.left() + progress * rect.width();
+
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 2.0, theme.mantle);
+    for i in 0..rect.width() as usize {
+        let x = rect.left() + i as f32 + 0.5;
+        let peak = match waveform {
+            Some(waveform) if !waveform.peaks.is_empty() => {
+                let column = (i * waveform.peaks.len() / rect.width() as usize).min(waveform.peaks.len() - 1);
+                waveform.peaks[column].min(1.0)
+            }
+            _ => 0.0,
+        };
+        let half_height = (peak * rect.height() / 2.0).max(0.5);
+        let color = if x < played_x { theme.mauve } else { theme.overlay0 };
+        painter.vline(x, rect.center().y - half_height..=rect.center().y + half_height, egui::Stroke::new(1.0, color));
+    }
+    draw_loop_markers(ui, rect, loop_a, loop_b, total);
+    painter.vline(played_x, rect.y_range(), egui::Stroke::new(2.0, theme.text));
+
+    if response.clicked() || response.drag_stopped() { pointer } else { None }
+}
+
+// Shades the A-B section on the seekbar and marks its ends
+fn draw_loop_markers(ui: &egui::Ui, rect: egui::Rect, a: Option<Duration>, b: Option<Duration>, total: Duration) {
+    if total.is_zero() {
+        return;
+    }
+    let rail = rect.x_range();
+    let x_for = |time: Duration| rail.min + (time.as_secs_f32() / total.as_secs_f32()).min(1.0) * rail.span();
+    let color = ui.visuals().selection.bg_fill;
+    let stroke = egui::Stroke::new(2.0, color);
+    let painter = ui.painter();
+
+    if let (Some(a), Some(b)) = (a, b) {
+        let section = egui::Rect::from_x_y_ranges(x_for(a)..=x_for(b), rect.y_range());
+        painter.rect_filled(section, 0.0, color.gamma_multiply(0.3));
+    }
+    for marker in [a, b].into_iter().flatten() {
+        painter.vline(x_for(marker), rect.y_range(), stroke);
+    }
+}
+
+// Spectrum bars, lowest frequencies on the left, with the level meter beside them
+fn draw_visualizer(ui: &mut egui::Ui, analyzer: &Analyzer) {
+    let size = egui::vec2(ui.available_width(), VISUALIZER_HEIGHT);
+    let (rect, _) = ui.allocate_exact_size(size, egui::Sense::hover());
+    let visuals = ui.visuals();
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 2.0, visuals.extreme_bg_color);
+
+    let height_for = |db: f32| (db - FLOOR_DB) / -FLOOR_DB * rect.height();
+    let spectrum = egui::Rect::from_min_max(rect.min, egui::pos2(rect.right() - LEVEL_METER_WIDTH - 4.0, rect.bottom()));
+    let bar_width = spectrum.width() / analyzer.bands.len().max(1) as f32;
+    for (i, db) in analyzer.bands.iter().enumerate() {
+        let left = spectrum.left() + i as f32 * bar_width;
+        let bar = egui::Rect::from_min_max(
+            egui::pos2(left + 1.0, rect.bottom() - height_for(*db)),
+            egui::pos2(left + bar_width - 1.0, rect.bottom()),
+        );
+        painter.rect_filled(bar, 0.0, visuals.selection.bg_fill);
+    }
+
+    // RMS as a filled bar, the peak as a line above it
+    let meter = egui::Rect::from_min_max(egui::pos2(rect.right() - LEVEL_METER_WIDTH, rect.top()), rect.max);
+    let rms = egui::Rect::from_min_max(egui::pos2(meter.left(), rect.bottom() - height_for(analyzer.rms)), meter.max);
+    painter.rect_filled(rms, 0.0, visuals.selection.bg_fill);
+    let peak_color = if analyzer.peak >= 0.0 { egui::Color32::RED } else { visuals.strong_text_color() };
+    painter.hline(meter.x_range(), rect.bottom() - height_for(analyzer.peak), egui::Stroke::new(2.0, peak_color));
+}
+
+fn format_balance(balance: f64) -> String {
+    let percent = (balance.abs() * 100.0).round();
+    if percent == 0.0 {
+        "Center".to_string()
+    } else if balance < 0.0 {
+        format!("{}% L", percent)
+    } else {
+        format!("{}% R", percent)
+    }
+}
+
+fn format_sample_rate(sample_rate: u32) -> String {
+    format!("{:.1} kHz", sample_rate as f64 / 1000.0)
+}
@@ -0,0 +1,200 @@
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use rodio::source::SeekError;
+use rodio::Source;
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+use serde::{Deserialize, Serialize};
+
+/// Room for a few frames' worth of audio between UI repaints.
+pub const TAP_CAPACITY: usize = 16384;
+
+const FFT_SIZE: usize = 2048;
+const LOWEST_FREQUENCY: f32 = 30.0;
+const HIGHEST_FREQUENCY: f32 = 16000.0;
+/// Everything quieter than this is drawn as nothing.
+pub const FLOOR_DB: f32 = -80.0;
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VisualizerSettings {
+    pub enabled: bool,
+    pub band_count: usize,
+    /// How fast bars and meters fall back, in dB per second.
+    pub decay: f32,
+}
+
+impl Default for VisualizerSettings {
+    fn default() -> Self {
+        Self { enabled: true, band_count: 32, decay: 30.0 }
+    }
+}
+
+/// What the playback thread shares with whoever reads the tap.
+pub struct TapControl {
+    pub enabled: AtomicBool,
+    pub sample_rate: AtomicU32,
+}
+
+impl TapControl {
+    pub fn new() -> Self {
+        Self { enabled: AtomicBool::new(true), sample_rate: AtomicU32::new(44_100) }
+    }
+}
+
+/// Passes audio through unchanged, copying a mono mix of it into a ring
+/// buffer for the visualizer. Samples that don't fit are dropped.
+pub struct SampleTap<S> {
+    input: S,
+    producer: rtrb::Producer<f32>,
+    control: Arc<TapControl>,
+    // Sum of the frame so far and the index of the next sample in it
+    frame_sum: f32,
+    channel: usize,
+}
+
+impl<S: Source> SampleTap<S> {
+    pub fn new(input: S, producer: rtrb::Producer<f32>, control: Arc<TapControl>) -> Self {
+        Self { input, producer, control, frame_sum: 0.0, channel: 0 }
+    }
+
+    pub fn inner_mut(&mut self) -> &mut S {
+        &mut self.input
+    }
+}
+
+impl<S: Source> Iterator for SampleTap<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.input.next()?;
+        if !self.control.enabled.load(Ordering::Relaxed) {
+            return Some(sample);
+        }
+
+        let channels = (self.input.channels() as usize).max(1);
+        self.frame_sum += sample;
+        self.channel += 1;
+        if self.channel >= channels {
+            if self.producer.push(self.frame_sum / channels as f32).is_ok() {
+                self.control.sample_rate.store(self.input.sample_rate(), Ordering::Relaxed);
+            }
+            self.frame_sum = 0.0;
+            self.channel = 0;
+        }
+        Some(sample)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<S: Source> Source for SampleTap<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        self.input.current_span_len()
+    }
+
+    fn channels(&self) -> rodio::ChannelCount {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> rodio::SampleRate {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)?;
+        self.frame_sum = 0.0;
+        self.channel = 0;
+        Ok(())
+    }
+}
+
+/// Turns tapped samples into spectrum bars and peak/RMS levels, all in dBFS.
+pub struct Analyzer {
+    fft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+    // The last FFT_SIZE samples
+    history: VecDeque<f32>,
+    pub bands: Vec<f32>,
+    pub peak: f32,
+    pub rms: f32,
+}
+
+impl Analyzer {
+    pub fn new() -> Self {
+        let window = (0..FFT_SIZE)
+            .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / FFT_SIZE as f32).cos())
+            .collect();
+        Self {
+            fft: FftPlanner::new().plan_fft_forward(FFT_SIZE),
+            window,
+            history: VecDeque::from(vec![0.0; FFT_SIZE]),
+            bands: Vec::new(),
+            peak: FLOOR_DB,
+            rms: FLOOR_DB,
+        }
+    }
+
+    /// Takes in the samples played since the last call, `elapsed` ago.
+    pub fn update(&mut self, samples: &[f32], sample_rate: u32, elapsed: Duration, settings: &VisualizerSettings) {
+        let fall = settings.decay * elapsed.as_secs_f32();
+        self.bands.resize(settings.band_count, FLOOR_DB);
+        for band in &mut self.bands {
+            *band = (*band - fall).max(FLOOR_DB);
+        }
+        self.peak = (self.peak - fall).max(FLOOR_DB);
+        self.rms = (self.rms - fall).max(FLOOR_DB);
+
+        // Nothing new: just let everything fall
+        if samples.is_empty() {
+            return;
+        }
+
+        let peak = samples.iter().fold(0f32, |peak, sample| peak.max(sample.abs()));
+        let rms = (samples.iter().map(|sample| sample * sample).sum::<f32>() / samples.len() as f32).sqrt();
+        self.peak = self.peak.max(amplitude_to_db(peak));
+        self.rms = self.rms.max(amplitude_to_db(rms));
+
+        let keep = FFT_SIZE.saturating_sub(samples.len());
+        self.history.drain(..self.history.len() - keep);
+        self.history.extend(&samples[samples.len().saturating_sub(FFT_SIZE)..]);
+
+        let mut buffer: Vec<Complex<f32>> = self
+            .history
+            .iter()
+            .zip(&self.window)
+            .map(|(sample, weight)| Complex::new(sample * weight, 0.0))
+            .collect();
+        self.fft.process(&mut buffer);
+
+        // A full-scale sine comes out at 0 dB
+        let scale = 2.0 / self.window.iter().sum::<f32>();
+        let bin_width = sample_rate as f32 / FFT_SIZE as f32;
+        let highest = HIGHEST_FREQUENCY.min(sample_rate as f32 / 2.0);
+        let band_count = self.bands.len();
+
+        for (i, band) in self.bands.iter_mut().enumerate() {
+            // Bands are spaced evenly on a log scale
+            let edge = |i: usize| LOWEST_FREQUENCY * (highest / LOWEST_FREQUENCY).powf(i as f32 / band_count as f32);
+            let low = (edge(i) / bin_width) as usize;
+            let high = ((edge(i + 1) / bin_width) as usize).max(low + 1).min(FFT_SIZE / 2);
+            let magnitude = buffer[low.min(high - 1)..high]
+                .iter()
+                .fold(0f32, |max, bin| max.max(bin.norm()));
+            *band = band.max(amplitude_to_db(magnitude * scale));
+        }
+    }
+}
+
+fn amplitude_to_db(amplitude: f32) -> f32 {
+    (20.0 * amplitude.max(1e-6).log10()).max(FLOOR_DB)
+}
@@ -0,0 +1,98 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use crate::audio::{decode_file, read_track_id};
+use crate::worker::BackgroundWorker;
+
+/// How many peaks a waveform is boiled down to, whatever the track's length.
+const WAVEFORM_COLUMNS: usize = 1000;
+
+// Peaks are first gathered over 10 ms blocks, before the length is known
+const BLOCKS_PER_SECOND: u32 = 100;
+
+/// Overview of a track's loudness over time.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Waveform {
+    /// Highest absolute sample value in each stretch of the track, from start to end.
+    pub peaks: Vec<f32>,
+}
+
+impl Waveform {
+    /// Decodes a whole file and collects its peaks.
+    pub fn from_file(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut blocks = Vec::new();
+        let mut block_peak = 0f32;
+        let mut frames_in_block = 0;
+
+        decode_file(path, |samples, spec| {
+            let channels = spec.channels.count().max(1);
+            let frames_per_block = (spec.rate / BLOCKS_PER_SECOND).max(1);
+            for frame in samples.chunks(channels) {
+                block_peak = frame.iter().fold(block_peak, |peak, sample| peak.max(sample.abs()));
+                frames_in_block += 1;
+                if frames_in_block == frames_per_block {
+                    blocks.push(block_peak);
+                    block_peak = 0.0;
+                    frames_in_block = 0;
+                }
+            }
+        })?;
+        if frames_in_block > 0 {
+            blocks.push(block_peak);
+        }
+        if blocks.is_empty() {
+            return Err("Track has no audio".into());
+        }
+
+        let columns = blocks.len().min(WAVEFORM_COLUMNS);
+        let peaks = (0..columns)
+            .map(|i| {
+                let start = i * blocks.len() / columns;
+                let end = ((i + 1) * blocks.len() / columns).max(start + 1);
+                blocks[start..end].iter().fold(0f32, |peak, block| peak.max(*block))
+            })
+            .collect();
+        Ok(Self { peaks })
+    }
+
+    /// Loads the waveform of a file from the cache, working it out and
+    /// caching it first if need be. The cache is keyed by the track's audio,
+    /// so a moved or retagged file keeps its waveform.
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let id = read_track_id(path).ok_or("Track has no audio")?;
+        let cache_path = Self::cache_dir().join(format!("{}.json", id.as_str()));
+
+        if let Some(waveform) = fs::read_to_string(&cache_path)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+        {
+            return Ok(waveform);
+        }
+
+        let waveform = Self::from_file(path)?;
+        // Failing to cache only costs decoding the file again next time
+        let _ = waveform.save(&cache_path);
+        Ok(waveform)
+    }
+
+    fn save(&self, cache_path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        fs::create_dir_all(Self::cache_dir())?;
+        fs::write(cache_path, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    fn cache_dir() -> PathBuf {
+        PathBuf::from("./library/waveforms")
+    }
+}
+
+/// Loads waveforms on a worker thread so the UI never waits on decoding.
+/// Only the latest request matters, so skipping through tracks doesn't
+/// leave a backlog of waveforms nobody will look at.
+pub type WaveformLoader = BackgroundWorker<Result<Waveform, String>>;
+
+impl WaveformLoader {
+    pub fn new() -> Self {
+        Self::spawn_latest_only(|path| Waveform::load(path).map_err(|e| e.to_string()))
+    }
+}
@@ -0,0 +1,69 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+/// Runs a job for each queued file on a worker thread so the UI never waits
+/// on it, handing the results back through `poll`.
+pub struct BackgroundWorker<T> {
+    requests: Sender<PathBuf>,
+    results: Receiver<(PathBuf, T)>,
+    pending: HashSet<PathBuf>,
+    latest_only: bool,
+}
+
+impl<T: Send + 'static> BackgroundWorker<T> {
+    /// Starts a worker that runs `job` on every queued file, in order.
+    pub fn spawn(job: impl Fn(&Path) -> T + Send + 'static) -> Self {
+        Self::start(job, false)
+    }
+
+    /// Starts a worker that only keeps up with the most recent request:
+    /// anything queued before it that hasn't been started yet is dropped.
+    pub fn spawn_latest_only(job: impl Fn(&Path) -> T + Send + 'static) -> Self {
+        Self::start(job, true)
+    }
+
+    fn start(job: impl Fn(&Path) -> T + Send + 'static, latest_only: bool) -> Self {
+        let (requests, request_rx) = mpsc::channel::<PathBuf>();
+        let (result_tx, results) = mpsc::channel();
+
+        thread::spawn(move || {
+            while let Ok(mut path) = request_rx.recv() {
+                if latest_only && let Some(newest) = request_rx.try_iter().last() {
+                    path = newest;
+                }
+                let result = job(&path);
+                if result_tx.send((path, result)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Self { requests, results, pending: HashSet::new(), latest_only }
+    }
+
+    pub fn queue(&mut self, path: &Path) {
+        if self.latest_only {
+            // Whatever was waiting is dropped by the worker
+            self.pending.clear();
+        }
+        if self.pending.insert(path.to_path_buf()) {
+            let _ = self.requests.send(path.to_path_buf());
+        }
+    }
+
+    /// Returns the jobs that finished since the last call, by the file they
+    /// were queued for.
+    pub fn poll(&mut self) -> Vec<(PathBuf, T)> {
+        let finished: Vec<_> = self.results.try_iter().collect();
+        for (path, _) in &finished {
+            self.pending.remove(path);
+        }
+        finished
+    }
+
+    pub fn pending_count(&self) -> usize {
+        self.pending.len()
+    }
+}
@@ -0,0 +1,111 @@
+use std::f32::consts::TAU;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+use rust_audio_player::audio::AudioFile;
+use rust_audio_player::output::OutputTarget;
+use rust_audio_player::player::Player;
+use rust_audio_player::playlist::Playlist;
+use rust_audio_player::queue::{Advance, PlayQueue};
+
+// Null output runs this many times faster than real time
+const SPEED: f32 = 10.0;
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+// Writes a short sine tone, so the test needs no audio files of its own
+fn write_tone(path: &Path, frequency: f32) {
+    let spec = hound::WavSpec {
+        channels: 2,
+        sample_rate: 44_100,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::create(path, spec).unwrap();
+    for i in 0..spec.sample_rate / 4 {
+        let sample = ((TAU * frequency * i as f32 / spec.sample_rate as f32).sin() * 8000.0) as i16;
+        writer.write_sample(sample).unwrap();
+        writer.write_sample(sample).unwrap();
+    }
+    writer.finalize().unwrap();
+}
+
+// Each test gets its own folder, as they run in parallel
+fn fixture_dir(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("auto_advance_{}_{}", test, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn wait_for_end(player: &mut Player) {
+    let started = Instant::now();
+    while !player.track_finished() {
+        assert!(started.elapsed() < TIMEOUT, "track never finished");
+        player.update();
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+#[test]
+fn playlist_moves_on_when_a_track_ends() {
+    let dir = fixture_dir("playlist");
+    let first = dir.join("first.wav");
+    let second = dir.join("second.wav");
+    write_tone(&first, 440.0);
+    write_tone(&second, 660.0);
+
+    let mut playlist = Playlist::new(vec![AudioFile::new(first.clone()), AudioFile::new(second.clone())]);
+    let mut queue = PlayQueue::open(dir.join("queue.json"));
+    let mut player = Player::new(&OutputTarget::Null { speed: SPEED }).unwrap();
+
+    player.play(&first, 1.0).unwrap();
+    assert!(!player.track_finished());
+    wait_for_end(&mut player);
+
+    assert!(matches!(queue.advance(Some(&mut playlist)), Advance::Playlist));
+    let next = playlist.current().unwrap().path.clone();
+    assert_eq!(next, second);
+
+    player.play(&next, 1.0).unwrap();
+    wait_for_end(&mut player);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn queued_tracks_play_before_the_playlist() {
+    let dir = fixture_dir("queued");
+    let first = dir.join("first.wav");
+    let queued = dir.join("queued.wav");
+    write_tone(&first, 440.0);
+    write_tone(&queued, 880.0);
+
+    let mut playlist = Playlist::new(vec![AudioFile::new(first.clone())]);
+    let mut queue = PlayQueue::open(dir.join("queue.json"));
+    queue.add(AudioFile::new(queued.clone()));
+
+    match queue.advance(Some(&mut playlist)) {
+        Advance::Queued(track) => assert_eq!(track.path, queued),
+        _ => panic!("queued track wasn't played first"),
+    }
+    assert!(queue.is_empty());
+    assert_eq!(playlist.current().unwrap().path, first);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn shuffle_plays_every_track_once_before_starting_over() {
+    let files = (0..5).map(|i| AudioFile::new(PathBuf::from(format!("missing_{}.wav", i)))).collect();
+    let mut playlist = Playlist::new(files);
+    playlist.set_shuffle(true);
+
+    let mut played = vec![playlist.current().unwrap().path.clone()];
+    while !playlist.is_last() {
+        assert_eq!(playlist.upcoming().len(), 5 - played.len());
+        played.push(playlist.next().unwrap().path.clone());
+    }
+    played.sort();
+    played.dedup();
+    assert_eq!(played.len(), 5);
+    assert!(playlist.upcoming().is_empty());
+}
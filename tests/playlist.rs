@@ -0,0 +1,38 @@
+use std::path::PathBuf;
+use rust_audio_player::audio::AudioFile;
+use rust_audio_player::playlist::Playlist;
+
+// Tracks that aren't on disk are fine for anything that doesn't play them
+fn playlist_of(count: usize) -> Playlist {
+    Playlist::new((0..count).map(|i| AudioFile::new(PathBuf::from(format!("missing_{}.wav", i)))).collect())
+}
+
+#[test]
+fn next_after_previous_returns_to_the_track_left() {
+    let mut playlist = playlist_of(3);
+
+    playlist.next();
+    playlist.next();
+    assert_eq!(playlist.current_index(), Some(2));
+    playlist.previous();
+    playlist.previous();
+    assert_eq!(playlist.current_index(), Some(0));
+    playlist.next();
+    assert_eq!(playlist.current_index(), Some(1));
+    playlist.next();
+    assert_eq!(playlist.current_index(), Some(2));
+}
+
+#[test]
+fn starting_shuffled_plays_each_track_once_before_starting_over() {
+    let mut playlist = playlist_of(5);
+
+    playlist.start_shuffled();
+    let mut played = vec![playlist.current_index().unwrap()];
+    while !playlist.is_last() {
+        playlist.next();
+        played.push(playlist.current_index().unwrap());
+    }
+    played.sort();
+    assert_eq!(played, vec![0, 1, 2, 3, 4]);
+}
@@ -0,0 +1,73 @@
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+use rust_audio_player::audio::AudioFile;
+use rust_audio_player::smart_playlist::{Rule, RuleField, RuleOp, SmartOrder, SmartPlaylist};
+
+const DAY: Duration = Duration::from_secs(86_400);
+
+// Tracks that aren't on disk are fine for anything that doesn't play them
+fn library() -> Vec<AudioFile> {
+    let now = SystemTime::now();
+    let mut jazz = AudioFile::new(PathBuf::from("music/jazz.wav"));
+    jazz.tags.genre = Some("Jazz".to_string());
+    jazz.tags.year = Some(1959);
+    jazz.stats.play_count = 10;
+    jazz.stats.last_played = Some(now - 2 * DAY);
+
+    let mut rock = AudioFile::new(PathBuf::from("music/rock.wav"));
+    rock.tags.genre = Some("Rock".to_string());
+    rock.tags.year = Some(1971);
+    rock.stats.play_count = 3;
+    rock.stats.last_played = Some(now - 40 * DAY);
+
+    // No tags and never played
+    let untagged = AudioFile::new(PathBuf::from("inbox/untagged.wav"));
+
+    vec![jazz, rock, untagged]
+}
+
+fn rule(field: RuleField, op: RuleOp, text: &str, number: u32) -> Rule {
+    Rule { field, op, text: text.to_string(), number }
+}
+
+fn matching(rules: Vec<Rule>, match_all: bool) -> Vec<String> {
+    let playlist = SmartPlaylist { rules, match_all, limit: None, order: SmartOrder::TrackList };
+    playlist
+        .evaluate(&library())
+        .iter()
+        .map(|file| file.path.file_stem().unwrap().to_string_lossy().into_owned())
+        .collect()
+}
+
+#[test]
+fn text_rules_ignore_case_and_surrounding_spaces() {
+    assert_eq!(matching(vec![rule(RuleField::Genre, RuleOp::Is, " jazz ", 0)], true), ["jazz"]);
+    assert_eq!(matching(vec![rule(RuleField::Genre, RuleOp::IsNot, "JAZZ", 0)], true), ["rock", "untagged"]);
+    assert_eq!(matching(vec![rule(RuleField::Path, RuleOp::Contains, "MUSIC/", 0)], true), ["jazz", "rock"]);
+}
+
+#[test]
+fn number_rules_compare_with_the_value() {
+    assert_eq!(matching(vec![rule(RuleField::Year, RuleOp::Is, "", 1971)], true), ["rock"]);
+    assert_eq!(matching(vec![rule(RuleField::Year, RuleOp::IsNot, "", 1971)], true), ["jazz", "untagged"]);
+    assert_eq!(matching(vec![rule(RuleField::PlayCount, RuleOp::AtLeast, "", 3)], true), ["jazz", "rock"]);
+    assert_eq!(matching(vec![rule(RuleField::PlayCount, RuleOp::AtMost, "", 3)], true), ["rock", "untagged"]);
+}
+
+#[test]
+fn date_rules_count_days_back_from_now() {
+    assert_eq!(matching(vec![rule(RuleField::LastPlayed, RuleOp::Within, "", 7)], true), ["jazz"]);
+    assert_eq!(matching(vec![rule(RuleField::LastPlayed, RuleOp::NotWithin, "", 7)], true), ["rock", "untagged"]);
+}
+
+#[test]
+fn tracks_match_every_rule_or_any_rule() {
+    let rules = || {
+        vec![
+            rule(RuleField::Genre, RuleOp::Is, "rock", 0),
+            rule(RuleField::PlayCount, RuleOp::AtLeast, "", 5),
+        ]
+    };
+    assert!(matching(rules(), true).is_empty());
+    assert_eq!(matching(rules(), false), ["jazz", "rock"]);
+}
@@ -0,0 +1,55 @@
+use std::cmp::Ordering;
+use std::path::PathBuf;
+use rust_audio_player::audio::AudioFile;
+use rust_audio_player::sort::{natural_cmp, sort_files, sort_for_showing, SortColumn, SortKey};
+
+// Tracks that aren't on disk are fine for anything that doesn't play them
+fn track(path: &str, album: &str, track_number: u32, play_count: u32) -> AudioFile {
+    let mut file = AudioFile::new(PathBuf::from(path));
+    file.tags.album = Some(album.to_string());
+    file.tags.track_number = Some(track_number);
+    file.stats.play_count = play_count;
+    file
+}
+
+fn paths(files: &[AudioFile]) -> Vec<&str> {
+    files.iter().map(|file| file.path.to_str().unwrap()).collect()
+}
+
+#[test]
+fn numbers_compare_by_value() {
+    assert_eq!(natural_cmp("2 - x", "10 - y"), Ordering::Less);
+    assert_eq!(natural_cmp("Track 10", "track 9"), Ordering::Greater);
+    assert_eq!(natural_cmp("a", "A"), Ordering::Equal);
+    assert_eq!(natural_cmp("07", "7"), Ordering::Greater);
+}
+
+#[test]
+fn later_keys_break_ties_in_earlier_ones() {
+    let mut files = vec![
+        track("c.wav", "B", 1, 3),
+        track("a.wav", "A", 2, 5),
+        track("b.wav", "B", 2, 5),
+        track("d.wav", "A", 1, 3),
+    ];
+    let keys = [
+        SortKey { column: SortColumn::PlayCount, descending: true },
+        SortKey { column: SortColumn::TrackNumber, descending: false },
+    ];
+
+    sort_files(&mut files, &keys);
+    assert_eq!(paths(&files), ["a.wav", "b.wav", "c.wav", "d.wav"]);
+}
+
+#[test]
+fn a_single_album_opens_in_track_order_whatever_the_sort() {
+    let mut files = vec![track("a.wav", "A", 3, 0), track("b.wav", "A", 1, 0), track("c.wav", "A", 2, 0)];
+    let keys = [SortKey { column: SortColumn::Path, descending: true }];
+
+    sort_for_showing(&mut files, &keys);
+    assert_eq!(paths(&files), ["b.wav", "c.wav", "a.wav"]);
+
+    files.push(track("d.wav", "B", 1, 0));
+    sort_for_showing(&mut files, &keys);
+    assert_eq!(paths(&files), ["d.wav", "c.wav", "b.wav", "a.wav"]);
+}
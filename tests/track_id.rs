@@ -0,0 +1,56 @@
+use std::path::{Path, PathBuf};
+use rust_audio_player::audio::AudioFile;
+use rust_audio_player::replaygain::{write_track_tags, ReplayGain};
+
+const SAMPLE_RATE: u32 = 44_100;
+
+// Writes two seconds of mono audio, sample by sample from `sample`
+fn write_wav(path: &Path, sample: impl Fn(u32) -> f32) {
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate: SAMPLE_RATE,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::create(path, spec).unwrap();
+    for i in 0..SAMPLE_RATE * 2 {
+        writer.write_sample((sample(i) * 8000.0) as i16).unwrap();
+    }
+    writer.finalize().unwrap();
+}
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("track_id_{}_{}.wav", name, std::process::id()))
+}
+
+#[test]
+fn tagging_a_track_keeps_its_id() {
+    let path = temp_path("tagged");
+    write_wav(&path, |i| (i as f32 * 0.06).sin());
+    let before = AudioFile::new(path.clone()).id;
+    assert!(before.is_some());
+
+    let replay_gain = ReplayGain { track_gain: Some(-3.5), track_peak: Some(0.9), ..Default::default() };
+    write_track_tags(&path, &replay_gain).unwrap();
+    let after = AudioFile::new(path.clone());
+    assert!(after.id == before);
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn tracks_that_open_the_same_way_get_different_ids() {
+    // A second of silence, well past what's hashed from the start, then different tones
+    let first = temp_path("same_start_a");
+    let second = temp_path("same_start_b");
+    write_wav(&first, |i| if i < SAMPLE_RATE { 0.0 } else { (i as f32 * 0.06).sin() });
+    write_wav(&second, |i| if i < SAMPLE_RATE { 0.0 } else { (i as f32 * 0.09).sin() });
+
+    let first_id = AudioFile::new(first.clone()).id;
+    let second_id = AudioFile::new(second.clone()).id;
+    assert!(first_id.is_some() && second_id.is_some());
+    assert!(first_id != second_id);
+
+    std::fs::remove_file(&first).unwrap();
+    std::fs::remove_file(&second).unwrap();
+}